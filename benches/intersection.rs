@@ -35,6 +35,53 @@ mod benches {
         b.iter(|| parse_with_quality(MOCK_ACCEPT_LANGUAGE));
     }
 
+    fn mock_headers() -> Vec<String> {
+        (0..10_000)
+            .map(|i| {
+                let language = AVIALABLE_LANGUAGES[i % AVIALABLE_LANGUAGES.len()];
+                format!("{}, {};q=0.{}", MOCK_ACCEPT_LANGUAGE, language, i % 10)
+            })
+            .collect()
+    }
+
+    #[bench]
+    fn bench_parse_batch(b: &mut Bencher) {
+        let headers = mock_headers();
+        let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+        b.iter(|| parse_batch(&headers));
+    }
+
+    #[bench]
+    fn bench_parse_batch_with_a_loop(b: &mut Bencher) {
+        let headers = mock_headers();
+        b.iter(|| headers.iter().map(|h| parse(h)).collect::<Vec<_>>());
+    }
+
+    #[bench]
+    fn bench_parse_strict(b: &mut Bencher) {
+        b.iter(|| parse_strict(MOCK_ACCEPT_LANGUAGE));
+    }
+
+    #[bench]
+    fn bench_parse_specific_first(b: &mut Bencher) {
+        b.iter(|| parse_specific_first(MOCK_ACCEPT_LANGUAGE));
+    }
+
+    #[bench]
+    fn bench_parse_to_map(b: &mut Bencher) {
+        b.iter(|| parse_to_map(MOCK_ACCEPT_LANGUAGE));
+    }
+
+    #[bench]
+    fn bench_parse_to_btree(b: &mut Bencher) {
+        b.iter(|| parse_to_btree(MOCK_ACCEPT_LANGUAGE));
+    }
+
+    #[bench]
+    fn bench_accepts_any(b: &mut Bencher) {
+        b.iter(|| accepts_any(MOCK_ACCEPT_LANGUAGE));
+    }
+
     #[bench]
     fn bench_intersections(b: &mut Bencher) {
         b.iter(|| intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES));
@@ -0,0 +1,61 @@
+//! A default list of supported languages, for small applications that don't want to maintain
+//! their own. It's only compiled with the `defaults` feature.
+//!
+//! The list is a curated selection of widely used languages and regional variants, not an
+//! exhaustive one, and may grow in minor releases.
+use crate::select_lossy;
+
+/// Widely used languages, sorted in byte order so they can be passed straight to
+/// [`intersection_ordered`](crate::intersection_ordered).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::defaults::COMMON_LANGUAGES;
+/// use accept_language::intersection_ordered;
+///
+/// let common_languages = intersection_ordered("en-US, de;q=0.5", COMMON_LANGUAGES);
+/// assert_eq!(common_languages, vec!["en-US", "de"]);
+/// ```
+pub const COMMON_LANGUAGES: &[&str] = &[
+    "ar", "bn", "cs", "da", "de", "el", "en", "en-GB", "en-US", "es", "es-419", "es-ES", "fa",
+    "fi", "fr", "fr-CA", "he", "hi", "hu", "id", "it", "ja", "ko", "ms", "nb", "nl", "pl", "pt",
+    "pt-BR", "pt-PT", "ro", "ru", "sv", "sw", "th", "tr", "uk", "vi", "zh-Hans", "zh-Hant",
+];
+
+/// Pick the best of the [`COMMON_LANGUAGES`] for a raw Accept-Language header value, like
+/// [`select_lossy`](crate::select_lossy), or `default` when none of them is acceptable.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::defaults::negotiate_common;
+///
+/// assert_eq!(negotiate_common("en-AU, ga;q=0.5", "fr"), "en");
+/// assert_eq!(negotiate_common("tlh", "fr"), "fr");
+/// ```
+pub fn negotiate_common(raw_languages: &str, default: &str) -> String {
+    select_lossy(raw_languages, COMMON_LANGUAGES, default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{negotiate_common, COMMON_LANGUAGES};
+    use crate::intersection_ordered;
+
+    #[test]
+    fn it_sorts_the_common_languages() {
+        assert!(COMMON_LANGUAGES.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn it_negotiates_against_the_common_languages() {
+        assert_eq!(negotiate_common("zh-TW, tlh;q=0.5", "en"), "zh-Hant");
+        assert_eq!(negotiate_common("pt-BR, pt;q=0.8", "en"), "pt-BR");
+        assert_eq!(negotiate_common("", "en"), "en");
+        assert_eq!(
+            intersection_ordered("es-419, es;q=0.9, en;q=0.1", COMMON_LANGUAGES),
+            vec!["es-419", "es", "en"]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! C bindings for embedding `accept-language` in servers that aren't written in Rust. They're
+//! only compiled with the `capi` feature.
+//!
+//! A library that C code can link against is built with
+//! `cargo rustc --release --features capi --crate-type cdylib` (or `staticlib`).
+//!
+//! # Ownership
+//!
+//! Strings passed into these functions are only borrowed for the duration of the call. The array
+//! returned by [`accept_language_intersection`] and every string inside it belong to this
+//! library, and must be released with exactly one call to [`accept_language_free`] rather than
+//! with `free(3)`.
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+/// C version of [`intersection`](crate::intersection). `raw` is the Accept-Language header
+/// value and `supported` is an array of `n` supported languages, all as NUL-terminated UTF-8
+/// strings.
+///
+/// On success the common languages are returned as an array of NUL-terminated strings, and the
+/// length of that array is written to `out_len`. An empty intersection is still a success: the
+/// returned pointer isn't null and has to be freed, but `out_len` is `0`.
+///
+/// A null pointer is returned, and `out_len` is set to `0` when it isn't null itself, if any
+/// pointer argument is null (`supported` may only be null when `n` is `0`), or if any string
+/// isn't valid UTF-8.
+///
+/// # Safety
+///
+/// `raw` and each of the `n` entries of `supported` must be null or point to a NUL-terminated
+/// string, `supported` must be null or point to `n` readable pointers, and `out_len` must be null
+/// or point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn accept_language_intersection(
+    raw: *const c_char,
+    supported: *const *const c_char,
+    n: usize,
+    out_len: *mut usize,
+) -> *mut *mut c_char {
+    if out_len.is_null() {
+        return ptr::null_mut();
+    }
+    *out_len = 0;
+    if raw.is_null() || (supported.is_null() && n > 0) {
+        return ptr::null_mut();
+    }
+    let raw_languages = match CStr::from_ptr(raw).to_str() {
+        Ok(raw_languages) => raw_languages,
+        Err(_) => return ptr::null_mut(),
+    };
+    let supported = if n == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(supported, n)
+    };
+    let mut supported_languages = Vec::with_capacity(n);
+    for &language in supported {
+        if language.is_null() {
+            return ptr::null_mut();
+        }
+        match CStr::from_ptr(language).to_str() {
+            Ok(language) => supported_languages.push(language),
+            Err(_) => return ptr::null_mut(),
+        }
+    }
+    // The languages come from `raw`, which can't contain a NUL byte, so no conversion fails.
+    let common_languages: Box<[*mut c_char]> =
+        crate::intersection(raw_languages, &supported_languages)
+            .into_iter()
+            .filter_map(|l| CString::new(l).ok())
+            .map(CString::into_raw)
+            .collect();
+    *out_len = common_languages.len();
+    Box::into_raw(common_languages) as *mut *mut c_char
+}
+
+/// Free an array returned by [`accept_language_intersection`], along with every string in it.
+/// Passing a null pointer does nothing.
+///
+/// # Safety
+///
+/// `languages` must be null or a pointer returned by [`accept_language_intersection`] that hasn't
+/// been freed yet, and `len` must be the length written to its `out_len`.
+#[no_mangle]
+pub unsafe extern "C" fn accept_language_free(languages: *mut *mut c_char, len: usize) {
+    if languages.is_null() {
+        return;
+    }
+    let languages = Box::from_raw(ptr::slice_from_raw_parts_mut(languages, len));
+    for &language in languages.iter() {
+        drop(CString::from_raw(language));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_language_free, accept_language_intersection};
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+    use std::slice;
+
+    fn intersect(raw: &CString, supported: &[CString]) -> Option<Vec<String>> {
+        let supported: Vec<*const c_char> = supported.iter().map(|l| l.as_ptr()).collect();
+        let mut len = usize::MAX;
+        unsafe {
+            let languages = accept_language_intersection(
+                raw.as_ptr(),
+                supported.as_ptr(),
+                supported.len(),
+                &mut len,
+            );
+            if languages.is_null() {
+                assert_eq!(len, 0);
+                return None;
+            }
+            let common_languages = slice::from_raw_parts(languages, len)
+                .iter()
+                .map(|&l| CStr::from_ptr(l).to_str().unwrap().to_owned())
+                .collect();
+            accept_language_free(languages, len);
+            Some(common_languages)
+        }
+    }
+
+    #[test]
+    fn it_returns_the_intersection_through_ffi() {
+        let raw = CString::new("en-US, de;q=0.7, jp;q=0.1").unwrap();
+        let supported = vec![CString::new("jp").unwrap(), CString::new("en-US").unwrap()];
+        assert_eq!(
+            intersect(&raw, &supported),
+            Some(vec![String::from("en-US"), String::from("jp")])
+        );
+        assert_eq!(intersect(&raw, &[]), Some(vec![]));
+    }
+
+    #[test]
+    fn it_returns_null_for_invalid_ffi_input() {
+        let invalid_utf8 = CString::new(vec![0xff, 0xfe]).unwrap();
+        let supported = vec![CString::new("en-US").unwrap()];
+        assert_eq!(intersect(&invalid_utf8, &supported), None);
+
+        let raw = CString::new("en-US").unwrap();
+        let mut len = usize::MAX;
+        unsafe {
+            assert!(accept_language_intersection(ptr::null(), ptr::null(), 0, &mut len).is_null());
+            assert_eq!(len, 0);
+            assert!(accept_language_intersection(raw.as_ptr(), ptr::null(), 1, &mut len).is_null());
+            let supported = [ptr::null()];
+            assert!(
+                accept_language_intersection(raw.as_ptr(), supported.as_ptr(), 1, &mut len)
+                    .is_null()
+            );
+            assert!(
+                accept_language_intersection(raw.as_ptr(), ptr::null(), 0, ptr::null_mut())
+                    .is_null()
+            );
+            accept_language_free(ptr::null_mut(), 0);
+        }
+    }
+}
@@ -15,27 +15,37 @@
 //! let user_languages = parse("en-US, en-GB;q=0.5");
 //! let common_languages = intersection("en-US, en-GB;q=0.5", &["en-US", "de", "en-GB"]);
 //! ```
-use std::cmp::Ordering;
+#[cfg(feature = "defaults")]
+pub mod defaults;
+#[cfg(feature = "capi")]
+pub mod ffi;
+mod tag;
+
+pub use tag::LanguageTag;
+
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
 use std::str;
-use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Language {
     name: String,
-    quality: f32,
+    quality: Quality,
 }
 
 impl Eq for Language {}
 
 impl Ord for Language {
     fn cmp(&self, other: &Language) -> Ordering {
-        if self.quality > other.quality {
-            Ordering::Less
-        } else if self.quality < other.quality {
-            Ordering::Greater
-        } else {
-            Ordering::Equal
-        }
+        self.cmp_quality(other).then_with(|| {
+            let name = self.name.bytes().map(|b| b.to_ascii_lowercase());
+            let other_name = other.name.bytes().map(|b| b.to_ascii_lowercase());
+            name.cmp(other_name)
+        })
     }
 }
 
@@ -47,326 +57,5497 @@ impl PartialOrd for Language {
 
 impl PartialEq for Language {
     fn eq(&self, other: &Language) -> bool {
-        self.quality == other.quality && self.name.to_lowercase() == other.name.to_lowercase()
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Language {
     fn new(tag: &str) -> Language {
-        let tag_parts: Vec<&str> = tag.split(';').collect();
-        let name = tag_parts[0].to_string();
-        let quality = match tag_parts.len() {
-            1 => 1.0,
-            _ => Language::quality_with_default(tag_parts[1]),
+        let (name, quality) = Language::split(tag);
+        Language {
+            name: name.to_string(),
+            quality,
+        }
+    }
+
+    /// Split a single header segment like `en-US;q=0.5` into its trimmed name and quality.
+    ///
+    /// This never panics: the name is whatever precedes the first `;` (possibly empty, which
+    /// callers drop), and the quality comes from the first parameter named `q`, so empty
+    /// parameters like the one in `en;;q=0.5` and other parameters are skipped. A quality that is
+    /// missing or has no value, as in `en`, `en;`, `en;q` and `en;q=`, defaults to `1` while one
+    /// that can't be parsed becomes `0`. Only iterators, `split_once` and slicing after ASCII
+    /// bytes are used, so no shape of input is indexed out of bounds.
+    fn split(tag: &str) -> (&str, Quality) {
+        let (name, value) = Language::split_raw(tag);
+        let quality = value.map_or(Quality::MAX, |value| {
+            Quality(Language::parse_millis(value).map_or(0, |(millis, _)| millis))
+        });
+        (name, quality)
+    }
+
+    /// Split a single header segment into its trimmed name and the value of its `q` parameter,
+    /// or `None` when that's missing or empty.
+    fn split_raw(tag: &str) -> (&str, Option<&str>) {
+        let (name, param) = Language::split_param(tag);
+        (name, param.and_then(Language::quality_value))
+    }
+
+    /// Split a single header segment into its trimmed name and its whole `q` parameter, like
+    /// `q=0.5`, as it was given.
+    fn split_param(tag: &str) -> (&str, Option<&str>) {
+        let mut tag_parts = tag.split(';');
+        let name = tag_parts.next().unwrap_or("").trim();
+        let param = tag_parts.find(|param| {
+            let key = param.split_once('=').map_or(*param, |(key, _)| key);
+            key.trim().eq_ignore_ascii_case("q")
+        });
+        (name, param)
+    }
+
+    /// Check a quality value against the `qvalue` grammar of
+    /// [RFC 7231](https://www.rfc-editor.org/rfc/rfc7231#section-5.3.1): `0` with up to three
+    /// decimals, or `1` with up to three zero decimals.
+    fn is_qvalue(value: &str) -> bool {
+        let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+        fraction.len() <= 3
+            && match integer {
+                "0" => fraction.bytes().all(|b| b.is_ascii_digit()),
+                "1" => fraction.bytes().all(|b| b == b'0'),
+                _ => false,
+            }
+    }
+
+    /// The value of a `q` parameter like `q=0.5`, or `None` when it has no value. Some clients
+    /// quote it (`q="0.5"`), which isn't allowed but is unambiguous, so a single pair of
+    /// surrounding quotes is removed.
+    fn quality_value(raw_quality: &str) -> Option<&str> {
+        let value = raw_quality.split_once('=').map_or("", |(_, q)| q).trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        Some(value).filter(|v| !v.is_empty())
+    }
+
+    /// Parse a quality value from its decimal digits straight into thousandths, so `0.1` and
+    /// `0.100` are the same quality and nothing is lost to `f32` rounding. An optional sign, then
+    /// digits with at most one `.` are accepted, so `.5` and `+0.5` are fine but `0.5e0` isn't.
+    /// Values above `1` are clamped to `1`, negative values to `0`, and fractional digits beyond
+    /// the third are truncated, which is reported as `true` alongside the thousandths.
+    fn parse_millis(value: &str) -> Option<(u16, bool)> {
+        let (negative, digits) = match value.as_bytes().first() {
+            Some(b'-') => (true, &value[1..]),
+            Some(b'+') => (false, &value[1..]),
+            _ => (false, value),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let millis = if negative {
+            0
+        } else if integer.bytes().any(|b| b != b'0') {
+            1000
+        } else {
+            fraction
+                .bytes()
+                .chain(b"000".iter().copied())
+                .take(3)
+                .fold(0, |millis, b| millis * 10 + u16::from(b - b'0'))
         };
-        Language { name, quality }
+        Some((millis, fraction.len() > 3))
     }
 
+    /// Parse a whole parameter like `q=0.5` into a quality, with the same rules as
+    /// [`split`](Language::split).
+    #[cfg(test)]
     fn quality_with_default(raw_quality: &str) -> f32 {
-        let quality_parts: Vec<&str> = raw_quality.split('=').collect();
-        match quality_parts.len() {
-            2 => f32::from_str(quality_parts[1]).unwrap_or(0.0),
-            _ => 0.0,
+        Language::quality_value(raw_quality).map_or(1.0, |value| {
+            Language::parse_millis(value).map_or(0.0, |(millis, _)| f32::from(millis) / 1000.0)
+        })
+    }
+
+    /// Orders by descending quality only, so languages with the same quality keep the order
+    /// they had in the header once sorted.
+    fn cmp_quality(&self, other: &Language) -> Ordering {
+        other.quality.cmp(&self.quality)
+    }
+}
+
+/// The errors returned by the fallible functions of this crate. Most functions don't fail, and
+/// quietly skip or clamp what they can't use instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The same language tag was given more than one quality, e.g. `en;q=0.5, en;q=0.9`. The
+    /// qualities are listed in header order. Returned by [`parse_strict`](parse_strict).
+    ConflictingQuality { tag: String, qualities: Vec<f32> },
+    /// The quality of a language tag isn't a valid qvalue, e.g. `en;q=0.5000`, which has more
+    /// than the three decimal places the spec allows. Returned by [`parse_strict`](parse_strict).
+    InvalidQuality { tag: String, quality: String },
+    /// A segment doesn't start with a language, e.g. `123`: its first subtag isn't `*` or one to
+    /// eight ASCII letters. Returned by [`parse_strict`](parse_strict).
+    NotALanguageTag { tag: String },
+    /// A value outside of `0..=1`, or `NaN`, was given as a [`Quality`], or as a quality to
+    /// [`parse_strict`](parse_strict).
+    QualityOutOfRange { quality: f32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConflictingQuality { tag, qualities } => {
+                write!(f, "the language `{}` has conflicting qualities", tag)?;
+                for (i, quality) in qualities.iter().enumerate() {
+                    write!(f, "{} {}", if i == 0 { ":" } else { "," }, quality)?;
+                }
+                Ok(())
+            }
+            Error::InvalidQuality { tag, quality } => write!(
+                f,
+                "the language `{}` has an invalid quality: {}",
+                tag, quality
+            ),
+            Error::NotALanguageTag { tag } => write!(f, "`{}` isn't a language tag", tag),
+            Error::QualityOutOfRange { quality } => {
+                write!(f, "the quality {} is outside of the range 0 to 1", quality)
+            }
         }
     }
 }
 
-/// Parse a raw Accept-Language header value into an ordered list of language tags.
-/// This should return the exact same list as `window.navigator.languages` in supported browsers.
+impl error::Error for Error {}
+
+/// The quality (or weight) a user gave to a language, between `0` and `1` with at most three
+/// decimals as allowed by the spec. A quality of `0` means the language is not acceptable.
 ///
 /// # Example
 ///
 /// ```
-/// use accept_language::parse;
+/// use accept_language::Quality;
+/// use std::convert::TryFrom;
 ///
-/// let user_languages = parse("en-US, en-GB;q=0.5");
+/// let quality = Quality::try_from(0.5).unwrap();
+/// assert_eq!(quality, Quality::from_millis(500).unwrap());
+/// assert!(quality < Quality::MAX);
+/// assert!(Quality::try_from(1.5).is_err());
 /// ```
-pub fn parse(raw_languages: &str) -> Vec<String> {
-    let stripped_languages = raw_languages.to_owned().replace(' ', "");
-    let language_strings: Vec<&str> = stripped_languages.split(',').collect();
-    let mut languages: Vec<Language> = language_strings.iter().map(|l| Language::new(l)).collect();
-    languages.sort();
-    languages
-        .iter()
-        .map(|l| l.name.to_owned())
-        .filter(|l| !l.is_empty())
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Quality(u16);
+
+impl Quality {
+    /// The quality of a language that is not acceptable, `0`.
+    pub const MIN: Quality = Quality(0);
+    /// The quality of a language without an explicit quality, `1`.
+    pub const MAX: Quality = Quality(1000);
+
+    /// Create a quality from thousandths, so `500` is a quality of `0.5`. Values above `1000` are
+    /// rejected.
+    pub fn from_millis(millis: u16) -> Result<Quality, Error> {
+        if millis <= 1000 {
+            Ok(Quality(millis))
+        } else {
+            Err(Error::QualityOutOfRange {
+                quality: f32::from(millis) / 1000.0,
+            })
+        }
+    }
+
+    /// The quality in thousandths.
+    pub fn as_millis(self) -> u16 {
+        self.0
+    }
+
+    /// The quality as a number between `0` and `1`.
+    pub fn as_f32(self) -> f32 {
+        f32::from(self.0) / 1000.0
+    }
+
+    /// Clamp any `f32` into a quality, for header values that are out of range.
+    fn clamped(quality: f32) -> Quality {
+        Quality(quality_to_millis(quality))
+    }
 }
 
-/// Similar to [`parse`](parse) but with quality `f32` appended to notice if it is a default value.
-/// is used by [`intersection_with_quality`](intersection_with_quality) and
-/// [`intersection_ordered_with_quality`](intersection_ordered_with_quality).
+impl TryFrom<f32> for Quality {
+    type Error = Error;
+
+    /// Rounds to the nearest thousandth, rejecting values outside of `0..=1` and `NaN`.
+    fn try_from(quality: f32) -> Result<Quality, Error> {
+        if (0.0..=1.0).contains(&quality) {
+            Ok(Quality::clamped(quality))
+        } else {
+            Err(Error::QualityOutOfRange { quality })
+        }
+    }
+}
+
+impl From<Quality> for f32 {
+    fn from(quality: Quality) -> f32 {
+        quality.as_f32()
+    }
+}
+
+impl fmt::Display for Quality {
+    /// Formats the quality the way it's written in a header, see
+    /// [`format_quality`](format_quality).
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_quality(self.as_f32()))
+    }
+}
+
+/// Builds an Accept-Language header value, for HTTP clients that send one rather than parse it.
+/// Languages are written in the order they were added unless
+/// [`sort_by_quality`](AcceptLanguageBuilder::sort_by_quality) is set, and a quality of `1` is
+/// left out since it's the default.
 ///
 /// # Example
 ///
 /// ```
-/// use accept_language::parse_with_quality;
+/// use accept_language::AcceptLanguageBuilder;
 ///
-/// let user_languages = parse_with_quality("en-US, en-GB;q=0.5");
-/// assert_eq!(user_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// let header = AcceptLanguageBuilder::new()
+///     .add("en-US")
+///     .add_with_quality("de", 0.7)
+///     .unwrap()
+///     .build();
+/// assert_eq!(header, "en-US, de;q=0.7");
 /// ```
-pub fn parse_with_quality(raw_languages: &str) -> Vec<(String, f32)> {
-    let stripped_languages = raw_languages.to_owned().replace(' ', "");
-    let language_strings: Vec<&str> = stripped_languages.split(',').collect();
-    let mut languages: Vec<Language> = language_strings.iter().map(|l| Language::new(l)).collect();
-    languages.sort();
-    languages
-        .iter()
-        .map(|l| (l.name.to_owned(), l.quality))
-        .filter(|l| !l.0.is_empty())
-        .collect()
+#[derive(Debug, Clone, Default)]
+pub struct AcceptLanguageBuilder {
+    languages: Vec<(String, Quality)>,
+    sort_by_quality: bool,
 }
 
-/// Compare an Accept-Language header value with your application's supported languages to find
-/// the common languages that could be presented to a user.
-///
-/// # Example
+impl AcceptLanguageBuilder {
+    /// Create a builder without any languages.
+    pub fn new() -> AcceptLanguageBuilder {
+        AcceptLanguageBuilder::default()
+    }
+
+    /// Add a language with the default quality of `1`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, tag: &str) -> AcceptLanguageBuilder {
+        self.languages.push((tag.to_string(), Quality::MAX));
+        self
+    }
+
+    /// Add a language with an explicit quality, which is rounded to the nearest thousandth.
+    /// Qualities outside of `0..=1` are rejected.
+    pub fn add_with_quality(
+        mut self,
+        tag: &str,
+        quality: f32,
+    ) -> Result<AcceptLanguageBuilder, Error> {
+        let quality = Quality::try_from(quality)?;
+        self.languages.push((tag.to_string(), quality));
+        Ok(self)
+    }
+
+    /// Write the languages by descending quality instead of in the order they were added.
+    /// Languages with the same quality keep the order they were added in.
+    pub fn sort_by_quality(mut self, sort_by_quality: bool) -> AcceptLanguageBuilder {
+        self.sort_by_quality = sort_by_quality;
+        self
+    }
+
+    /// Build the header value, like `en-US, de;q=0.7`.
+    pub fn build(&self) -> String {
+        let mut languages: Vec<&(String, Quality)> = self.languages.iter().collect();
+        if self.sort_by_quality {
+            languages.sort_by_key(|(_, quality)| Reverse(*quality));
+        }
+        languages
+            .into_iter()
+            .map(|(tag, quality)| {
+                if *quality == Quality::MAX {
+                    tag.clone()
+                } else {
+                    format!("{};q={}", tag, quality)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+/// Matches user languages against a list of supported languages that's prepared once, for
+/// servers that negotiate on every request.
 ///
-/// ```
-/// use accept_language::intersection;
+/// The supported languages are normalized when the matcher is created: underscores become
+/// hyphens and the casing of [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) is
+/// applied, so `en_us` becomes `en-US`. Entries that are the same once normalized are only kept
+/// the first time.
 ///
-/// let common_languages = intersection("en-US, en-GB;q=0.5", &["en-US", "de", "en-GB"]);
-/// ```
-pub fn intersection(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
-    let user_languages = parse(raw_languages);
-    user_languages
-        .into_iter()
-        .filter(|l| supported_languages.contains(&l.as_str()))
-        .collect()
-}
-/// Similar to [`intersection`](intersection) but using binary sort. The supported languages
-/// MUST be in alphabetical order, to find the common languages that could be presented
-/// to a user. Executes roughly 25% faster.
+/// Some clients send three-letter ISO 639-2/T codes like `deu` instead of `de`. With
+/// [`three_letter_codes`](Matcher::three_letter_codes) enabled, the common ones are mapped to their
+/// two-letter form before matching. The table is curated and doesn't cover every language; codes
+/// that aren't in it are matched unchanged.
 ///
 /// # Example
 ///
 /// ```
-/// use accept_language::intersection_ordered;
+/// use accept_language::Matcher;
 ///
-/// let common_languages = intersection_ordered("en-US, en-GB;q=0.5", &["de", "en-GB", "en-US"]);
+/// let matcher = Matcher::new(&["en_US", "en-US", "de"]);
+/// assert_eq!(matcher.supported_languages(), &["en-US", "de"]);
+/// assert_eq!(matcher.intersection("en-us, de;q=0.5"), vec!["en-US", "de"]);
 /// ```
-pub fn intersection_ordered(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
-    let user_languages = parse(raw_languages);
-    user_languages
-        .into_iter()
-        .filter(|l| supported_languages.binary_search(&l.as_str()).is_ok())
-        .collect()
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    supported_languages: Vec<String>,
+    three_letter_codes: bool,
 }
-/// Similar to [`intersection`](intersection) but with the quality as `f32` appended for each language.
-/// This enables distinction between the default language of a user (value 1.0) and the
-/// best match. If you don't want to assign your users immediatly to a non-default choice and you plan to add
-/// more languages later on in your webserver.
+
+impl Matcher {
+    /// Create a matcher for the supported languages, normalizing and deduplicating them.
+    pub fn new(supported_languages: &[&str]) -> Matcher {
+        let normalized = supported_languages
+            .iter()
+            .map(|language| canonical_case(&language.replace('_', "-")));
+        Matcher {
+            supported_languages: dedup_languages(normalized),
+            three_letter_codes: false,
+        }
+    }
+
+    /// Map three-letter ISO 639-2/T codes sent by the user, like `eng`, to their two-letter form,
+    /// like `en`, before matching. It's off by default.
+    pub fn three_letter_codes(mut self, three_letter_codes: bool) -> Matcher {
+        self.three_letter_codes = three_letter_codes;
+        self
+    }
+
+    /// The normalized supported languages, in the order they were first given.
+    pub fn supported_languages(&self) -> &[String] {
+        &self.supported_languages
+    }
+
+    /// Similar to [`intersection`](intersection), but user languages are normalized the same
+    /// way before matching and the normalized supported languages are returned, each once. A
+    /// supported language ending in `-*` returns the normalized user language it matched.
+    pub fn intersection(&self, raw_languages: &str) -> Vec<String> {
+        let mut common_languages: Vec<String> = Vec::new();
+        for language in parse_languages(raw_languages) {
+            let mut tag = language.name.replace('_', "-");
+            if self.three_letter_codes {
+                tag = two_letter_code(&tag).unwrap_or(tag);
+            }
+            let supported =
+                self.supported_languages
+                    .iter()
+                    .find_map(|l| match l.strip_suffix("-*") {
+                        Some(prefix) if has_prefix(&tag, prefix) => Some(canonical_case(&tag)),
+                        None if l.eq_ignore_ascii_case(&tag) => Some(l.clone()),
+                        _ => None,
+                    });
+            if let Some(supported) = supported {
+                if !common_languages.contains(&supported) {
+                    common_languages.push(supported);
+                }
+            }
+        }
+        common_languages
+    }
+}
+
+/// A parsed Accept-Language header value, for negotiating several times without parsing the
+/// header again. The methods behave like the functions of the same name.
 ///
 /// # Example
 ///
 /// ```
-/// use accept_language::intersection_with_quality;
+/// use accept_language::AcceptLanguage;
 ///
-/// let common_languages = intersection_with_quality("en-US, en-GB;q=0.5", &["en-US", "de", "en-GB"]);
-/// assert_eq!(common_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// let accept_language = AcceptLanguage::parse("en-US, de;q=0.5");
+/// assert_eq!(accept_language.languages(), vec!["en-US", "de"]);
+/// assert_eq!(accept_language.intersection(&["de", "en"]), vec!["de"]);
+/// assert_eq!(accept_language.lossy_intersection(&["de", "en"]), vec!["en", "de"]);
+/// assert_eq!(accept_language.select(&["fr", "de"], "fr"), "de");
 /// ```
-pub fn intersection_with_quality(
-    raw_languages: &str,
-    supported_languages: &[&str],
-) -> Vec<(String, f32)> {
-    let user_languages = parse_with_quality(raw_languages);
-    user_languages
-        .into_iter()
-        .filter(|l| supported_languages.contains(&l.0.as_str()))
-        .collect()
+#[derive(Debug, Clone)]
+pub struct AcceptLanguage {
+    languages: Vec<Language>,
 }
 
-/// Similar to [`intersection_with_quality`](intersection_with_quality). The supported languages MUST
-/// be in alphabetical order, to find the common languages that could be presented to a user.
-/// Executes roughly 25% faster.
+impl AcceptLanguage {
+    /// Parse a raw Accept-Language header value, like [`parse`](parse).
+    pub fn parse(raw_languages: &str) -> AcceptLanguage {
+        AcceptLanguage {
+            languages: parse_languages(raw_languages),
+        }
+    }
+
+    /// The user languages sorted by quality, like [`parse`](parse).
+    pub fn languages(&self) -> Vec<&str> {
+        self.languages.iter().map(|l| l.name.as_str()).collect()
+    }
+
+    /// The user languages that are supported, like [`intersection`](intersection).
+    pub fn intersection(&self, supported_languages: &[&str]) -> Vec<String> {
+        dedup_languages(
+            self.languages
+                .iter()
+                .filter(|l| is_supported(&l.name, supported_languages))
+                .map(|l| l.name.clone()),
+        )
+    }
+
+    /// The supported languages that serve the user, like
+    /// [`intersection_lossy`](intersection_lossy).
+    pub fn lossy_intersection(&self, supported_languages: &[&str]) -> Vec<String> {
+        lossy_intersection(&self.languages, supported_languages).0
+    }
+
+    /// The supported language to serve, or `default`, like [`select`](select).
+    pub fn select(&self, supported_languages: &[&str], default: &str) -> String {
+        select_from(&self.languages, supported_languages, default)
+    }
+}
+
+/// A configurable negotiation policy, for combining matching modes that the `intersection_*`
+/// functions only offer one at a time.
+///
+/// By default a negotiator matches exactly but ignoring case, and returns the supported languages
+/// in the order of the user languages they matched, each once. Languages with a quality of `0`
+/// are never returned. Each option is off by default, except
+/// [`case_insensitive`](Negotiator::case_insensitive).
 ///
 /// # Example
 ///
 /// ```
-/// use accept_language::intersection_ordered_with_quality;
+/// use accept_language::Negotiator;
 ///
-/// let common_languages = intersection_ordered_with_quality("en-US, en-GB;q=0.5", &["de", "en-GB", "en-US"]);
-/// assert_eq!(common_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// let negotiator = Negotiator::new().lossy(true).min_quality(0.3);
+/// let common_languages = negotiator.negotiate("en-US, de;q=0.5, fr;q=0.2", &["fr", "de", "en"]);
+/// assert_eq!(common_languages, vec!["en", "de"]);
 /// ```
-pub fn intersection_ordered_with_quality(
-    raw_languages: &str,
-    supported_languages: &[&str],
-) -> Vec<(String, f32)> {
-    let user_languages = parse_with_quality(raw_languages);
-    user_languages
-        .into_iter()
-        .filter(|l| supported_languages.binary_search(&l.0.as_str()).is_ok())
-        .collect()
+#[derive(Debug, Clone)]
+pub struct Negotiator {
+    lossy: bool,
+    case_insensitive: bool,
+    min_quality: Quality,
+    server_order: bool,
+    wildcard: bool,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{
-        intersection, intersection_ordered, intersection_ordered_with_quality,
-        intersection_with_quality, parse, Language,
-    };
-
-    static MOCK_ACCEPT_LANGUAGE: &str = "en-US, de;q=0.7, zh-Hant, jp;q=0.1";
-    static AVIALABLE_LANGUAGES: &[&str] =
-        &["da", "de", "en-US", "it", "jp", "zh", "zh-Hans", "zh-Hant"];
-
-    #[test]
-    fn it_creates_a_new_language_from_a_string() {
-        let language = Language::new("en-US;q=0.7");
-        assert_eq!(
-            language,
-            Language {
-                name: String::from("en-US"),
-                quality: 0.7,
-            }
-        )
+impl Default for Negotiator {
+    fn default() -> Negotiator {
+        Negotiator {
+            lossy: false,
+            case_insensitive: true,
+            min_quality: Quality::MIN,
+            server_order: false,
+            wildcard: false,
+        }
     }
+}
 
-    #[test]
-    fn it_creates_a_new_language_from_a_string_with_lowercase_country() {
-        let language = Language::new("en-us;q=0.7");
-        assert_eq!(
-            language,
-            Language {
-                name: String::from("en-US"),
-                quality: 0.7,
-            }
-        )
+impl Negotiator {
+    /// Create a negotiator with the default policy.
+    pub fn new() -> Negotiator {
+        Negotiator::default()
     }
 
-    #[test]
-    fn it_creates_a_new_language_from_a_string_with_a_default_quality() {
-        let language = Language::new("en-US");
-        assert_eq!(
-            language,
-            Language {
-                name: String::from("en-US"),
-                quality: 1.0,
-            }
-        )
+    /// Fall back like [`intersection_lossy`](intersection_lossy) when a user language has no
+    /// exact match.
+    pub fn lossy(mut self, lossy: bool) -> Negotiator {
+        self.lossy = lossy;
+        self
     }
 
-    #[test]
-    fn it_parses_quality() {
-        let quality = Language::quality_with_default("q=0.5");
-        assert_eq!(quality, 0.5)
+    /// Compare languages ignoring case, which is the default.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Negotiator {
+        self.case_insensitive = case_insensitive;
+        self
     }
 
-    #[test]
-    fn it_parses_an_invalid_quality() {
-        let quality = Language::quality_with_default("q=yolo");
-        assert_eq!(quality, 0.0)
+    /// Skip user languages with a quality below `min_quality`, which is clamped to `0..=1` and
+    /// rounded to the nearest thousandth.
+    pub fn min_quality(mut self, min_quality: f32) -> Negotiator {
+        self.min_quality = Quality::clamped(min_quality);
+        self
     }
 
-    #[test]
-    fn it_parses_a_valid_accept_language_header() {
-        let user_languages = parse(MOCK_ACCEPT_LANGUAGE);
-        assert_eq!(
-            user_languages,
-            vec![
-                String::from("en-US"),
-                String::from("zh-Hant"),
-                String::from("de"),
-                String::from("jp"),
-            ]
-        )
+    /// Return the common languages in the order of the supported languages instead of the order
+    /// of the user languages.
+    pub fn server_order(mut self, server_order: bool) -> Negotiator {
+        self.server_order = server_order;
+        self
     }
 
-    #[test]
-    fn it_parses_an_empty_accept_language_header() {
-        let user_languages = parse("");
-        assert_eq!(user_languages.len(), 0)
+    /// Let the user's `*` match every supported language that didn't match before it.
+    pub fn wildcard(mut self, wildcard: bool) -> Negotiator {
+        self.wildcard = wildcard;
+        self
     }
 
-    #[test]
-    fn it_parses_an_invalid_accept_language_header() {
-        let user_languages_one = parse("q");
-        let user_languages_two = parse(";q");
-        let user_languages_three = parse("q-");
-        let user_languages_four = parse("en;q=");
-        assert_eq!(user_languages_one, vec![String::from("q")]);
-        assert_eq!(user_languages_two.len(), 0);
-        assert_eq!(user_languages_three, vec![String::from("q-")]);
-        assert_eq!(user_languages_four, vec![String::from("en")])
+    /// Find the supported languages that serve the user according to this policy.
+    pub fn negotiate(&self, raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+        let user_languages = parse_languages(raw_languages);
+        let find = |tag: &str| {
+            supported_languages.iter().copied().find(|l| {
+                if self.case_insensitive {
+                    l.eq_ignore_ascii_case(tag)
+                } else {
+                    *l == tag
+                }
+            })
+        };
+        let is_rejected = |tag: &str| {
+            user_languages
+                .iter()
+                .any(|l| l.quality == Quality::MIN && find(&l.name) == Some(tag))
+        };
+        let mut common_languages: Vec<&str> = Vec::new();
+        for language in &user_languages {
+            if language.quality == Quality::MIN || language.quality < self.min_quality {
+                continue;
+            }
+            let matched: Vec<&str> = if language.name == "*" {
+                if self.wildcard {
+                    supported_languages
+                        .iter()
+                        .copied()
+                        .filter(|&l| !is_rejected(l))
+                        .collect()
+                } else {
+                    Vec::new()
+                }
+            } else {
+                find(&language.name)
+                    .or_else(|| {
+                        find_fallback(&language.name, find)
+                            .filter(|&l| self.lossy && !is_rejected(l))
+                    })
+                    .into_iter()
+                    .collect()
+            };
+            for supported in matched {
+                if !common_languages.contains(&supported) {
+                    common_languages.push(supported);
+                }
+            }
+        }
+        if self.server_order {
+            common_languages.sort_by_key(|&l| supported_languages.iter().position(|&s| s == l));
+        }
+        common_languages.into_iter().map(str::to_string).collect()
     }
+}
 
-    #[test]
-    fn it_sorts_languages_by_quality() {
-        let user_languages = parse("en-US, de;q=0.1, jp;q=0.7");
-        assert_eq!(
-            user_languages,
-            vec![
-                String::from("en-US"),
-                String::from("jp"),
-                String::from("de"),
-            ]
-        )
-    }
+/// Split a raw Accept-Language header value into languages sorted by quality. Segments without a
+/// language name (empty or whitespace-only, with or without a quality) are dropped here, so no
+/// public function ever sees a `Language` with an empty name.
+fn parse_languages(raw_languages: &str) -> Vec<Language> {
+    let mut languages = split_languages(raw_languages);
+    languages.sort_by(Language::cmp_quality);
+    languages
+}
+
+/// Like [`parse_languages`] but only keeps the first of several languages with the same name,
+/// ignoring case. That's the one with the highest quality, since they're already sorted.
+fn parse_unique_languages(raw_languages: &str) -> Vec<Language> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut languages = parse_languages(raw_languages);
+    languages.retain(|l| seen.insert(l.name.to_ascii_lowercase()));
+    languages
+}
+
+/// Collect languages, keeping only the first occurrence of each one.
+fn dedup_languages(languages: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    languages
+        .filter(|language| seen.insert(language.clone()))
+        .collect()
+}
+
+/// Same as [`parse_languages`] but leaves the languages in header order.
+fn split_languages(raw_languages: &str) -> Vec<Language> {
+    let mut languages = Vec::new();
+    split_languages_into(raw_languages, &mut String::new(), &mut languages);
+    languages
+}
+
+/// Same as [`split_languages`] but appends to `languages`, using `stripped` as scratch space for
+/// the header without spaces, so both buffers can be reused across headers. Segments without a
+/// name, like those left by the commas in `,en-US,,de,`, are skipped before anything is
+/// allocated for them.
+fn split_languages_into(raw_languages: &str, stripped: &mut String, languages: &mut Vec<Language>) {
+    stripped.clear();
+    stripped.extend(unfold(raw_languages).chars().filter(|&c| c != ' '));
+    languages.extend(
+        stripped
+            .split(',')
+            .map(Language::split)
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, quality)| Language {
+                name: name.to_string(),
+                quality,
+            }),
+    );
+}
+
+/// Replace obsolete line folding, a CRLF followed by a space or tab, with a single space as
+/// described in [RFC 7230](https://www.rfc-editor.org/rfc/rfc7230#section-3.2.4). Some servers
+/// hand over folded header values as they were received.
+fn unfold(raw_languages: &str) -> Cow<'_, str> {
+    if raw_languages.contains("\r\n") {
+        Cow::Owned(raw_languages.replace("\r\n ", " ").replace("\r\n\t", " "))
+    } else {
+        Cow::Borrowed(raw_languages)
+    }
+}
+
+/// Check whether `tag` is longer than `prefix` and starts with all of its subtags, ignoring case.
+/// `en-US` has the prefix `en`, but `en` doesn't have the prefix `en` and `enx` doesn't either.
+fn has_prefix(tag: &str, prefix: &str) -> bool {
+    let (tag, prefix) = (tag.as_bytes(), prefix.as_bytes());
+    tag.len() > prefix.len()
+        && tag[prefix.len()] == b'-'
+        && tag[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Check whether a language range matches a tag under the Basic Filtering scheme of
+/// [RFC 4647](https://www.rfc-editor.org/rfc/rfc4647#section-3.3.1): the range is `*`, or equals
+/// the tag or one of its prefixes, ignoring case. The range `de` matches `de` and `de-CH`.
+/// Private use ranges like `x-pig` only match themselves.
+fn basic_match(range: &str, tag: &str) -> bool {
+    range == "*"
+        || range.eq_ignore_ascii_case(tag)
+        || (!is_private_use(range) && has_prefix(tag, range))
+}
+
+/// Check whether a tag is only a private use sequence like `x-pig-latin`. These have no standard
+/// language to fall back to, so they only ever match themselves.
+fn is_private_use(tag: &str) -> bool {
+    primary_language(tag).eq_ignore_ascii_case("x")
+}
+
+/// Check whether the user's `tag` is one of the supported languages. A supported entry ending in
+/// `-*`, like `en-*`, matches any tag that has the rest of it as a prefix, so `en-US` and `en-GB`
+/// but not `en` itself.
+fn is_supported(tag: &str, supported_languages: &[&str]) -> bool {
+    supported_languages
+        .iter()
+        .any(|&l| match l.strip_suffix("-*") {
+            Some(prefix) => has_prefix(tag, prefix),
+            None => l == tag,
+        })
+}
+
+/// Check the precondition of the `_ordered` functions in debug builds: the supported languages
+/// are sorted in the byte order that `binary_search` relies on.
+fn debug_assert_sorted(supported_languages: &[&str]) {
+    debug_assert!(
+        supported_languages.windows(2).all(|w| w[0] <= w[1]),
+        "the supported languages must be sorted, but got {:?}",
+        supported_languages
+    );
+}
+
+/// Apply the casing conventions of [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1):
+/// two-letter regions are uppercase, four-letter scripts are titlecase and everything else is
+/// lowercase, including anything after a single-letter subtag like `x`.
+fn canonical_case(tag: &str) -> String {
+    let mut canonical = String::with_capacity(tag.len());
+    for (i, (subtag, case)) in subtag_cases(tag).enumerate() {
+        if i > 0 {
+            canonical.push('-');
+        }
+        match case {
+            SubtagCase::Lower => canonical.push_str(&subtag.to_ascii_lowercase()),
+            SubtagCase::Upper => canonical.push_str(&subtag.to_ascii_uppercase()),
+            SubtagCase::Title => {
+                canonical.push_str(&subtag[..1].to_ascii_uppercase());
+                canonical.push_str(&subtag[1..].to_ascii_lowercase());
+            }
+        }
+    }
+    canonical
+}
+
+/// Check whether a tag is already cased like [`canonical_case`] would case it, without
+/// allocating.
+fn is_canonical_case(tag: &str) -> bool {
+    subtag_cases(tag).all(|(subtag, case)| match case {
+        SubtagCase::Lower => !subtag.bytes().any(|b| b.is_ascii_uppercase()),
+        SubtagCase::Upper => !subtag.bytes().any(|b| b.is_ascii_lowercase()),
+        SubtagCase::Title => {
+            !subtag[..1].bytes().any(|b| b.is_ascii_lowercase())
+                && !subtag[1..].bytes().any(|b| b.is_ascii_uppercase())
+        }
+    })
+}
+
+/// The casing [`canonical_case`] gives a subtag.
+enum SubtagCase {
+    Lower,
+    Upper,
+    Title,
+}
+
+/// Split a tag into subtags along with the casing each gets in
+/// [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1).
+fn subtag_cases(tag: &str) -> impl Iterator<Item = (&str, SubtagCase)> {
+    let mut after_singleton = false;
+    tag.split('-').enumerate().map(move |(i, subtag)| {
+        if subtag.len() == 1 {
+            after_singleton = true;
+        }
+        let case = if i == 0 || after_singleton {
+            SubtagCase::Lower
+        } else if subtag.len() == 2 {
+            SubtagCase::Upper
+        } else if subtag.len() == 4 && subtag.bytes().all(|b| b.is_ascii_alphabetic()) {
+            SubtagCase::Title
+        } else {
+            SubtagCase::Lower
+        };
+        (subtag, case)
+    })
+}
+
+/// The number of subtags in a tag, e.g. `3` for `en-Latn-US`.
+fn subtag_count(tag: &str) -> usize {
+    tag.split('-').count()
+}
+
+/// Round a quality to the thousandths allowed by the spec, clamped to `0..=1000`. `NaN` becomes
+/// `0` through the saturating cast.
+fn quality_to_millis(quality: f32) -> u16 {
+    (quality.clamp(0.0, 1.0) * 1000.0).round() as u16
+}
+
+/// Find the supported language equal to `tag`, ignoring case.
+fn find_supported<'a>(tag: &str, supported_languages: &[&'a str]) -> Option<&'a str> {
+    supported_languages
+        .iter()
+        .copied()
+        .find(|l| l.eq_ignore_ascii_case(tag))
+}
+
+/// The script implied by a region for the well-known Chinese cases, so a user asking for `zh-TW`
+/// matches a supported `zh-Hant`. Other languages rarely leave their script implicit in a way
+/// that matters for matching, so they aren't listed.
+const IMPLIED_SCRIPTS: &[(&str, &str)] = &[
+    ("zh-CN", "zh-Hans"),
+    ("zh-HK", "zh-Hant"),
+    ("zh-MO", "zh-Hant"),
+    ("zh-SG", "zh-Hans"),
+    ("zh-TW", "zh-Hant"),
+];
+
+/// The tag with the script implied by the region of `tag`, from [`IMPLIED_SCRIPTS`].
+fn implied_script(tag: &str) -> Option<&'static str> {
+    IMPLIED_SCRIPTS
+        .iter()
+        .find(|(region_tag, _)| region_tag.eq_ignore_ascii_case(tag))
+        .map(|(_, script_tag)| *script_tag)
+}
+
+/// The script a language is written in when its tag doesn't say, from the likely subtags of
+/// [CLDR](https://cldr.unicode.org), for the languages that are commonly written in more than one.
+/// Sorted by language.
+const DEFAULT_SCRIPTS: &[(&str, &str)] = &[
+    ("az", "Latn"),
+    ("bs", "Latn"),
+    ("ha", "Latn"),
+    ("kk", "Cyrl"),
+    ("ku", "Latn"),
+    ("mn", "Cyrl"),
+    ("ms", "Latn"),
+    ("pa", "Guru"),
+    ("sd", "Arab"),
+    ("sr", "Cyrl"),
+    ("tg", "Cyrl"),
+    ("uz", "Latn"),
+    ("zh", "Hans"),
+];
+
+/// The script of a tag: its script subtag if it has one, like `Latn` for `sr-Latn`, or else the
+/// script its region implies, like `Hant` for `zh-TW`.
+fn script(tag: &str) -> Option<&str> {
+    tag.split('-')
+        .nth(1)
+        .filter(|s| s.len() == 4 && s.bytes().all(|b| b.is_ascii_alphabetic()))
+        .or_else(|| implied_script(tag).and_then(|t| t.split('-').nth(1)))
+}
+
+/// Check whether `tag` can fall back to its base language without changing script, which it
+/// can't when its script isn't the default one of the language (see [`DEFAULT_SCRIPTS`]).
+/// `sr-Latn` can't fall back to `sr`, which is written in Cyrillic.
+fn keeps_script(tag: &str) -> bool {
+    let primary = primary_language(tag);
+    let default_script = DEFAULT_SCRIPTS
+        .iter()
+        .find(|(language, _)| language.eq_ignore_ascii_case(primary))
+        .map(|(_, script)| *script);
+    match (script(tag), default_script) {
+        (Some(script), Some(default_script)) => script.eq_ignore_ascii_case(default_script),
+        _ => true,
+    }
+}
+
+/// Find the supported language to fall back to when `tag` has no exact match: the script its
+/// region implies (see [`IMPLIED_SCRIPTS`]), or else its base language as long as that doesn't
+/// change the script, ignoring case.
+fn find_supported_fallback<'a>(tag: &str, supported_languages: &[&'a str]) -> Option<&'a str> {
+    find_fallback(tag, |candidate| {
+        find_supported(candidate, supported_languages)
+    })
+}
+
+/// Like [`find_supported_fallback`], with `find` looking up each candidate tag.
+fn find_fallback<'a>(tag: &str, find: impl Fn(&str) -> Option<&'a str>) -> Option<&'a str> {
+    if is_private_use(tag) {
+        return None;
+    }
+    implied_script(tag).and_then(&find).or_else(|| {
+        let primary = primary_language(tag);
+        if primary.len() < tag.len() && keeps_script(tag) {
+            find(primary)
+        } else {
+            None
+        }
+    })
+}
+
+/// Common ISO 639-2/T codes and their ISO 639-1 equivalents, sorted by the three-letter code. It's
+/// deliberately not exhaustive.
+const TWO_LETTER_CODES: &[(&str, &str)] = &[
+    ("ara", "ar"),
+    ("ces", "cs"),
+    ("dan", "da"),
+    ("deu", "de"),
+    ("ell", "el"),
+    ("eng", "en"),
+    ("fas", "fa"),
+    ("fin", "fi"),
+    ("fra", "fr"),
+    ("heb", "he"),
+    ("hin", "hi"),
+    ("hun", "hu"),
+    ("ind", "id"),
+    ("ita", "it"),
+    ("jpn", "ja"),
+    ("kor", "ko"),
+    ("msa", "ms"),
+    ("nld", "nl"),
+    ("nor", "no"),
+    ("pol", "pl"),
+    ("por", "pt"),
+    ("ron", "ro"),
+    ("rus", "ru"),
+    ("spa", "es"),
+    ("swe", "sv"),
+    ("tha", "th"),
+    ("tur", "tr"),
+    ("ukr", "uk"),
+    ("vie", "vi"),
+    ("zho", "zh"),
+];
+
+/// Replace a three-letter primary language from [`TWO_LETTER_CODES`] with its two-letter form,
+/// keeping the other subtags, so `deu-CH` becomes `de-CH`.
+fn two_letter_code(tag: &str) -> Option<String> {
+    let primary = primary_language(tag);
+    let index = TWO_LETTER_CODES
+        .binary_search_by_key(&primary.to_ascii_lowercase().as_str(), |&(code, _)| code)
+        .ok()?;
+    Some(format!(
+        "{}{}",
+        TWO_LETTER_CODES[index].1,
+        &tag[primary.len()..]
+    ))
+}
+
+/// The primary language subtag of a tag, e.g. `en` for `en-US`.
+fn primary_language(tag: &str) -> &str {
+    tag.split('-').next().unwrap_or(tag)
+}
+
+/// Iterate over the names and qualities of a raw Accept-Language header value in header order
+/// without allocating, for checks that don't need the sorted list. Segments without a name are
+/// skipped. Whitespace is trimmed around each part instead of being removed everywhere, which
+/// only differs from [`parse_languages`] for names with whitespace inside them.
+fn scan_languages(raw_languages: &str) -> impl Iterator<Item = (&str, Quality)> {
+    raw_languages
+        .split(',')
+        .map(Language::split)
+        .filter(|(name, _)| !name.is_empty())
+}
+
+/// Like [`scan_languages`] but spaces are removed everywhere and folded lines unfolded, like in
+/// [`parse_languages`], so the names are the same as there. Only the segments with whitespace
+/// inside them are copied.
+fn scan_stripped_languages(raw_languages: &str) -> impl Iterator<Item = (Cow<'_, str>, Quality)> {
+    raw_languages
+        .split(',')
+        .map(|segment| {
+            let segment = segment.trim();
+            if segment.contains(' ') || segment.contains("\r\n") {
+                let stripped: String = unfold(segment).chars().filter(|&c| c != ' ').collect();
+                let (name, quality) = Language::split(&stripped);
+                (Cow::Owned(name.to_string()), quality)
+            } else {
+                let (name, quality) = Language::split(segment);
+                (Cow::Borrowed(name), quality)
+            }
+        })
+        .filter(|(name, _)| !name.is_empty())
+}
+
+/// Entry point for the `language` fuzz target, which exercises the parsing of a single segment on
+/// its own. It's only compiled when fuzzing.
+#[cfg(fuzzing)]
+#[doc(hidden)]
+pub fn fuzz_language(tag: &str) {
+    let language = Language::new(tag);
+    assert!(language.quality <= Quality::MAX);
+    assert_eq!(language.name, language.name.trim());
+    let chain = fallback_chain(&language.name);
+    assert!(chain.len() <= subtag_count(&language.name));
+    let tag = LanguageTag::parse(&language.name);
+    let _ = (tag.script(), tag.region(), tag.canonical());
+}
+
+/// Parse a raw Accept-Language header value into an ordered list of language tags.
+/// This should return the exact same list as `window.navigator.languages` in supported browsers.
+///
+/// The `*` range, which stands for any language, is returned as `*`; see
+/// [`intersection_with_wildcard`](intersection_with_wildcard) to match it against supported
+/// languages. Segments without a language name, like `;q=0.5`, are always dropped. A quality that is
+/// missing or has no value, like `en;q=`, is the default quality of `1`, and one that can't be
+/// parsed is `0`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse;
+///
+/// let user_languages = parse("en-US, en-GB;q=0.5");
+/// ```
+pub fn parse(raw_languages: &str) -> Vec<String> {
+    parse_languages(raw_languages)
+        .into_iter()
+        .map(|l| l.name)
+        .collect()
+}
+
+/// Parse a POSIX locale environment variable like `LANGUAGE=en_US:de` or `LANG=en_US.UTF-8` into
+/// language tags, for command line tools. Locales are separated by `:`, their charset (after `.`)
+/// and modifier (after `@`) are removed and underscores become hyphens, so `de_DE.UTF-8@euro`
+/// becomes `de-DE`. The `C` and `POSIX` locales don't name a language and are skipped.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_posix;
+///
+/// assert_eq!(parse_posix("en_US.UTF-8:de:fr"), vec!["en-US", "de", "fr"]);
+/// ```
+pub fn parse_posix(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .map(|locale| {
+            let end = locale.find(['.', '@']).unwrap_or(locale.len());
+            locale[..end].trim()
+        })
+        .filter(|locale| !locale.is_empty() && *locale != "C" && *locale != "POSIX")
+        .map(|locale| locale.replace('_', "-"))
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but returns at most `max_tags` tags, taking at most
+/// `max_total_bytes` together, for proxies that need to bound the memory a header can make them
+/// allocate. Tags are taken in quality order until the next one would exceed either limit. Only
+/// the returned tags are copied out of the header. Like [`parse_cow`](parse_cow), whitespace is
+/// trimmed around each tag instead of being removed everywhere.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_bounded;
+///
+/// let user_languages = parse_bounded("en-US, de;q=0.5, zh-Hant-TW;q=0.1", 10, 8);
+/// assert_eq!(user_languages, vec!["en-US", "de"]);
+/// ```
+pub fn parse_bounded(raw_languages: &str, max_tags: usize, max_total_bytes: usize) -> Vec<String> {
+    let mut languages: Vec<(&str, Quality)> = scan_languages(raw_languages).collect();
+    languages.sort_by_key(|&(_, quality)| Reverse(quality));
+    let mut total_bytes = 0;
+    languages
+        .into_iter()
+        .take(max_tags)
+        .take_while(|(name, _)| {
+            total_bytes += name.len();
+            total_bytes <= max_total_bytes
+        })
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but every tag is reduced to its primary language in lowercase, for
+/// coarse statistics like how many users want English at all. Each primary language is only
+/// returned once, where its highest quality puts it. Private use tags like `x-klingon` have no
+/// primary language and are kept whole, in lowercase too.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_primary;
+///
+/// let user_languages = parse_primary("en-US, en-GB;q=0.5, de");
+/// assert_eq!(user_languages, vec!["en", "de"]);
+/// ```
+pub fn parse_primary(raw_languages: &str) -> Vec<String> {
+    dedup_languages(parse_languages(raw_languages).into_iter().map(|l| {
+        let key = if is_private_use(&l.name) {
+            &l.name
+        } else {
+            primary_language(&l.name)
+        };
+        key.to_ascii_lowercase()
+    }))
+}
+
+/// Similar to [`parse`](parse) but the tags are cased like
+/// [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) recommends, and borrowed from
+/// the header whenever they already are, so a typical header doesn't allocate any tag. Whitespace
+/// is trimmed around each tag instead of being removed everywhere, so a tag with whitespace
+/// inside it is kept as it is.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_cow;
+/// use std::borrow::Cow;
+///
+/// let user_languages = parse_cow("en-us, en-GB;q=0.5");
+/// assert_eq!(user_languages, vec!["en-US", "en-GB"]);
+/// assert!(matches!(user_languages[0], Cow::Owned(_)));
+/// assert!(matches!(user_languages[1], Cow::Borrowed(_)));
+/// ```
+pub fn parse_cow(raw_languages: &str) -> Vec<Cow<'_, str>> {
+    let mut languages: Vec<(&str, Quality)> = scan_languages(raw_languages).collect();
+    languages.sort_by_key(|&(_, quality)| Reverse(quality));
+    languages
+        .into_iter()
+        .map(|(name, _)| {
+            if is_canonical_case(name) {
+                Cow::Borrowed(name)
+            } else {
+                Cow::Owned(canonical_case(name))
+            }
+        })
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but with quality `f32` appended to notice if it is a default value.
+/// is used by [`intersection_with_quality`](intersection_with_quality) and
+/// [`intersection_ordered_with_quality`](intersection_ordered_with_quality).
+/// A language given more than once only appears once, with its highest quality.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_with_quality;
+///
+/// let user_languages = parse_with_quality("en-US, en-GB;q=0.5");
+/// assert_eq!(user_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// ```
+pub fn parse_with_quality(raw_languages: &str) -> Vec<(String, f32)> {
+    parse_unique_languages(raw_languages)
+        .into_iter()
+        .map(|l| (l.name, l.quality.as_f32()))
+        .collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but the qualities are divided by the
+/// highest one, so the preferred language has a quality of `1` and the ratios between the others
+/// are kept, rounded to the nearest thousandth. `de;q=0.8, en;q=0.4` becomes `de` with `1` and
+/// `en` with `0.5`. When every language has a quality of `0`, nothing is rescaled.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_rescaled_top;
+///
+/// let user_languages = parse_rescaled_top("de;q=0.8, en;q=0.4");
+/// assert_eq!(user_languages, vec![(String::from("de"), 1.0), (String::from("en"), 0.5)]);
+/// ```
+pub fn parse_rescaled_top(raw_languages: &str) -> Vec<(String, f32)> {
+    let languages = parse_unique_languages(raw_languages);
+    let top = languages
+        .first()
+        .map_or(0, |l| u32::from(l.quality.as_millis()));
+    languages
+        .into_iter()
+        .map(|l| {
+            let millis = u32::from(l.quality.as_millis());
+            let rescaled = (millis * 1000 + top / 2).checked_div(top).unwrap_or(millis);
+            (l.name, f32::from(rescaled as u16) / 1000.0)
+        })
+        .collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but also flags the languages with the
+/// default quality of `1`, whether it was given explicitly or left out, to tell the user's
+/// default languages from the fallbacks.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_with_default_flag;
+///
+/// let user_languages = parse_with_default_flag("nl, en-US;q=0.7");
+/// assert_eq!(
+///     user_languages,
+///     vec![(String::from("nl"), 1.0, true), (String::from("en-US"), 0.7, false)]
+/// );
+/// ```
+pub fn parse_with_default_flag(raw_languages: &str) -> Vec<(String, f32, bool)> {
+    parse_unique_languages(raw_languages)
+        .into_iter()
+        .map(|l| {
+            let is_default = l.quality == Quality::MAX;
+            (l.name, l.quality.as_f32(), is_default)
+        })
+        .collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but as generous with malformed input as
+/// it can be while still reading it the same way, and normalizing the tags. In order, each
+/// segment between commas
+///
+/// * loses all of its whitespace inside the tag, and around parameters, keys and values, so
+///   `En_us ; Q = +.5` is read like `En_us;Q=+.5`;
+/// * has its tag normalized: underscores become hyphens and the casing of
+///   [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) is applied, so `En_us` becomes
+///   `en-US`;
+/// * takes its quality from the first parameter named `q` or `Q`, skipping other parameters like
+///   `level=1`;
+/// * reads the quality with an optional `+` sign, without a leading `0` like `.5`, without
+///   decimals after the point like `1.`, and in double quotes like `"0.5"`.
+///
+/// Segments without a tag are skipped. A quality that still can't be read is `0`, and one that is
+/// missing or empty is `1`, like in [`parse`](parse). Languages that are the same once
+/// normalized are only returned once, with their highest quality.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_lenient;
+///
+/// let user_languages = parse_lenient("En_us ; Q = +.5, de;level=1;q=\"1.\"");
+/// assert_eq!(user_languages, vec![(String::from("de"), 1.0), (String::from("en-US"), 0.5)]);
+/// ```
+pub fn parse_lenient(raw_languages: &str) -> Vec<(String, f32)> {
+    let mut languages: Vec<(String, Quality)> = Vec::new();
+    for (name, quality) in raw_languages.split(',').map(Language::split) {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| if c == '_' { '-' } else { c })
+            .collect();
+        if !name.is_empty() {
+            languages.push((canonical_case(&name), quality));
+        }
+    }
+    languages.sort_by_key(|&(_, quality)| Reverse(quality));
+    let mut seen: HashSet<String> = HashSet::new();
+    languages
+        .into_iter()
+        .filter(|(name, _)| seen.insert(name.clone()))
+        .map(|(name, quality)| (name, quality.as_f32()))
+        .collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but with the quality as a [`Quality`],
+/// which is always in range and compares exactly.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{parse_with_quality_typed, Quality};
+///
+/// let user_languages = parse_with_quality_typed("en-US, en-GB;q=0.5");
+/// assert_eq!(user_languages[0], (String::from("en-US"), Quality::MAX));
+/// assert_eq!(user_languages[1].1.as_f32(), 0.5);
+/// ```
+pub fn parse_with_quality_typed(raw_languages: &str) -> Vec<(String, Quality)> {
+    parse_unique_languages(raw_languages)
+        .into_iter()
+        .map(|l| (l.name, l.quality))
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but returns each language as a [`LanguageTag`], so its subtags
+/// can be inspected and the `*` range is told apart from a language.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_language_tags;
+///
+/// let user_languages = parse_language_tags("en-US, *;q=0.5");
+/// assert_eq!(user_languages[0].region(), Some("US"));
+/// assert!(user_languages[1].is_wildcard());
+/// ```
+pub fn parse_language_tags(raw_languages: &str) -> Vec<LanguageTag> {
+    parse_languages(raw_languages)
+        .iter()
+        .map(|l| LanguageTag::parse(&l.name))
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but keeps the languages in the order the header lists them
+/// instead of sorting them by quality. Languages with a quality of `0` are dropped.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_unsorted;
+///
+/// assert_eq!(parse_unsorted("de;q=0.1, en, fr;q=0"), vec!["de", "en"]);
+/// ```
+pub fn parse_unsorted(raw_languages: &str) -> Vec<String> {
+    split_languages(raw_languages)
+        .into_iter()
+        .filter(|l| l.quality > Quality::MIN)
+        .map(|l| l.name)
+        .collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but with the position of each language
+/// in the header before sorting, counting from `0`. Segments without a language name aren't
+/// counted, and duplicates are kept, so sorting by the position gives back the header order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_indexed;
+///
+/// let user_languages = parse_indexed("de;q=0.5, en");
+/// assert_eq!(user_languages, vec![(String::from("en"), 1.0, 1), (String::from("de"), 0.5, 0)]);
+/// ```
+pub fn parse_indexed(raw_languages: &str) -> Vec<(String, f32, usize)> {
+    let mut languages: Vec<(usize, Language)> = split_languages(raw_languages)
+        .into_iter()
+        .enumerate()
+        .collect();
+    languages.sort_by(|(_, a), (_, b)| a.cmp_quality(b));
+    languages
+        .into_iter()
+        .map(|(i, l)| (l.name, l.quality.as_f32(), i))
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but rejects headers that [`parse`](parse) would quietly accept.
+/// A tag given more than one quality (compared case-insensitively) is reported as
+/// [`Error::ConflictingQuality`]; repeating a tag with the same quality is fine. Every quality
+/// has to follow the `qvalue` grammar of
+/// [RFC 7231](https://www.rfc-editor.org/rfc/rfc7231#section-5.3.1) exactly, with no sign, quotes
+/// or leading `.`, and at most three decimals. A number above `1` or below `0`, which
+/// [`parse`](parse) clamps, is reported as [`Error::QualityOutOfRange`], and anything else that
+/// doesn't follow the grammar, like `q=bogus`, `q=0.5000` or an empty `q=`, as
+/// [`Error::InvalidQuality`]. An explicit `q=0` is a valid way to reject a language and is kept.
+/// A segment whose first subtag isn't `*` or one to eight ASCII letters, like `123`, is reported
+/// as [`Error::NotALanguageTag`].
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{parse_strict, Error};
+///
+/// assert_eq!(parse_strict("en-US, de;q=0.5"), Ok(vec![String::from("en-US"), String::from("de")]));
+/// assert_eq!(
+///     parse_strict("en;q=0.5, en;q=0.9"),
+///     Err(Error::ConflictingQuality { tag: String::from("en"), qualities: vec![0.5, 0.9] })
+/// );
+/// ```
+pub fn parse_strict(raw_languages: &str) -> Result<Vec<String>, Error> {
+    let stripped: String = unfold(raw_languages)
+        .chars()
+        .filter(|&c| c != ' ')
+        .collect();
+    let mut languages = Vec::new();
+    for segment in stripped.split(',') {
+        let (name, param) = Language::split_param(segment);
+        if name.is_empty() {
+            continue;
+        }
+        let primary = primary_language(name);
+        let is_language =
+            (1..=8).contains(&primary.len()) && primary.bytes().all(|b| b.is_ascii_alphabetic());
+        if primary != "*" && !is_language {
+            return Err(Error::NotALanguageTag {
+                tag: name.to_string(),
+            });
+        }
+        if let Some(param) = param {
+            let value = param.split_once('=').map_or("", |(_, q)| q).trim();
+            if !Language::is_qvalue(value) {
+                let out_of_range = Language::parse_millis(value)
+                    .and_then(|_| value.parse::<f32>().ok())
+                    .filter(|quality| !(0.0..=1.0).contains(quality));
+                if let Some(quality) = out_of_range {
+                    return Err(Error::QualityOutOfRange { quality });
+                }
+                return Err(Error::InvalidQuality {
+                    tag: name.to_string(),
+                    quality: value.to_string(),
+                });
+            }
+        }
+        languages.push(Language::new(segment));
+    }
+    for (i, language) in languages.iter().enumerate() {
+        let mut qualities = vec![language.quality];
+        for other in &languages[i + 1..] {
+            if other.name.eq_ignore_ascii_case(&language.name)
+                && !qualities.contains(&other.quality)
+            {
+                qualities.push(other.quality);
+            }
+        }
+        if qualities.len() > 1 {
+            return Err(Error::ConflictingQuality {
+                tag: language.name.clone(),
+                qualities: qualities.into_iter().map(Quality::as_f32).collect(),
+            });
+        }
+    }
+    languages.sort_by(Language::cmp_quality);
+    Ok(languages.into_iter().map(|l| l.name).collect())
+}
+
+/// Similar to [`parse`](parse) but, among languages with the same quality, a tag is moved ahead of
+/// any shorter tag it extends, so `en, en-US` becomes `en-US, en`. Unrelated tags keep their
+/// header order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_specific_first;
+///
+/// let user_languages = parse_specific_first("en, de, en-US");
+/// assert_eq!(user_languages, vec!["en-US", "en", "de"]);
+/// ```
+pub fn parse_specific_first(raw_languages: &str) -> Vec<String> {
+    let mut languages: Vec<Language> = Vec::new();
+    let mut same_quality_start = 0;
+    for language in parse_languages(raw_languages) {
+        if languages
+            .last()
+            .is_some_and(|l| l.quality != language.quality)
+        {
+            same_quality_start = languages.len();
+        }
+        let position = languages[same_quality_start..]
+            .iter()
+            .position(|l| has_prefix(&language.name, &l.name))
+            .map_or(languages.len(), |p| same_quality_start + p);
+        languages.insert(position, language);
+    }
+    languages.into_iter().map(|l| l.name).collect()
+}
+
+/// Similar to [`parse`](parse) but, among languages with the same quality and the same primary
+/// language, those with more subtags come first, so `zh, zh-Hant, zh-Hans-CN` becomes
+/// `zh-Hans-CN, zh-Hant, zh`. Unlike [`parse_specific_first`](parse_specific_first), the tags
+/// don't have to extend each other. Each group of tags stays where its first tag was in the
+/// header, and tags with the same number of subtags keep their header order. Primary languages
+/// are compared ignoring case.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_specificity_within_language;
+///
+/// let user_languages = parse_specificity_within_language("zh-TW, en, zh-Hant-TW, de;q=0.5");
+/// assert_eq!(user_languages, vec!["zh-Hant-TW", "zh-TW", "en", "de"]);
+/// ```
+pub fn parse_specificity_within_language(raw_languages: &str) -> Vec<String> {
+    let languages = parse_languages(raw_languages);
+    let mut keyed: Vec<((usize, Reverse<usize>), &Language)> = languages
+        .iter()
+        .enumerate()
+        .map(|(i, language)| {
+            let primary = primary_language(&language.name);
+            let group = languages
+                .iter()
+                .position(|l| {
+                    l.quality == language.quality
+                        && primary_language(&l.name).eq_ignore_ascii_case(primary)
+                })
+                .unwrap_or(i);
+            ((group, Reverse(subtag_count(&language.name))), language)
+        })
+        .collect();
+    keyed.sort_by_key(|&(key, _)| key);
+    keyed.into_iter().map(|(_, l)| l.name.clone()).collect()
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but moves the languages the user
+/// explicitly rejected with a quality of `0` into a separate list, so they can be inspected or
+/// forwarded. Both lists keep the order [`parse_with_quality`](parse_with_quality) would give.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_with_rejections;
+///
+/// let (accepted, rejected) = parse_with_rejections("en-US, de;q=0, en-GB;q=0.5");
+/// assert_eq!(accepted, vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)]);
+/// assert_eq!(rejected, vec![String::from("de")]);
+/// ```
+pub fn parse_with_rejections(raw_languages: &str) -> (Vec<(String, f32)>, Vec<String>) {
+    let (accepted, rejected): (Vec<Language>, Vec<Language>) = parse_languages(raw_languages)
+        .into_iter()
+        .partition(|l| l.quality > Quality::MIN);
+    (
+        accepted
+            .into_iter()
+            .map(|l| (l.name, l.quality.as_f32()))
+            .collect(),
+        rejected.into_iter().map(|l| l.name).collect(),
+    )
+}
+
+/// Parse many raw Accept-Language header values at once, giving the same results as calling
+/// [`parse`](parse) on each of them. The scratch buffers used while parsing are shared across the
+/// batch, which saves allocations when processing stored headers in bulk.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_batch;
+///
+/// let user_languages = parse_batch(&["en-US, en-GB;q=0.5", "de"]);
+/// assert_eq!(user_languages, vec![vec!["en-US", "en-GB"], vec!["de"]]);
+/// ```
+pub fn parse_batch(raw_headers: &[&str]) -> Vec<Vec<String>> {
+    let mut stripped = String::new();
+    let mut languages = Vec::new();
+    raw_headers
+        .iter()
+        .map(|raw_languages| {
+            split_languages_into(raw_languages, &mut stripped, &mut languages);
+            languages.sort_by(Language::cmp_quality);
+            languages.drain(..).map(|l| l.name).collect()
+        })
+        .collect()
+}
+
+/// Similar to [`parse`](parse) but pairs each tag in the casing conventions of BCP 47 with the tag
+/// exactly as the user sent it, so the first can be used for matching and the second echoed back.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_with_original;
+///
+/// let user_languages = parse_with_original("en-us, zh-hant;q=0.5");
+/// assert_eq!(
+///     user_languages,
+///     vec![
+///         (String::from("en-US"), String::from("en-us")),
+///         (String::from("zh-Hant"), String::from("zh-hant")),
+///     ]
+/// );
+/// ```
+pub fn parse_with_original(raw_languages: &str) -> Vec<(String, String)> {
+    parse_languages(raw_languages)
+        .into_iter()
+        .map(|l| (canonical_case(&l.name), l.name))
+        .collect()
+}
+
+/// Parse a raw Accept-Language header value into a map of lowercased language tags to their
+/// quality, for direct lookups. The ordering of the header is lost, and when a tag appears more
+/// than once the highest quality is kept.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_to_map;
+///
+/// let user_languages = parse_to_map("en-US, de;q=0.7");
+/// assert_eq!(user_languages.get("de"), Some(&0.7));
+/// assert_eq!(user_languages.get("en-us"), Some(&1.0));
+/// ```
+pub fn parse_to_map(raw_languages: &str) -> HashMap<String, f32> {
+    let mut languages = HashMap::new();
+    for (name, quality) in parse_with_quality(raw_languages) {
+        let entry = languages.entry(name.to_lowercase()).or_insert(quality);
+        if quality > *entry {
+            *entry = quality;
+        }
+    }
+    languages
+}
+
+/// Parse a raw Accept-Language header value into language tags grouped by quality. Qualities are
+/// wrapped in [`Reverse`], so iterating the map yields the highest quality first. Tags within a
+/// quality keep their header order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_to_btree;
+///
+/// let user_languages = parse_to_btree("en-US, de;q=0.5, en-GB");
+/// let groups: Vec<_> = user_languages
+///     .into_iter()
+///     .map(|(quality, tags)| (quality.0.as_f32(), tags))
+///     .collect();
+/// assert_eq!(
+///     groups,
+///     vec![
+///         (1.0, vec![String::from("en-US"), String::from("en-GB")]),
+///         (0.5, vec![String::from("de")]),
+///     ]
+/// );
+/// ```
+pub fn parse_to_btree(raw_languages: &str) -> BTreeMap<Reverse<Quality>, Vec<String>> {
+    let mut languages: BTreeMap<Reverse<Quality>, Vec<String>> = BTreeMap::new();
+    for language in parse_languages(raw_languages) {
+        languages
+            .entry(Reverse(language.quality))
+            .or_default()
+            .push(language.name);
+    }
+    languages
+}
+
+/// Parse a raw Accept-Language header value into languages with their share of the total
+/// quality as whole percentages, like `45% English, 30% German` on a dashboard. The percentages
+/// always add up to `100`, using largest remainder rounding, unless there are no languages.
+/// Languages with a quality of `0` are dropped.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_percentages;
+///
+/// let user_languages = parse_percentages("en, de;q=0.5");
+/// assert_eq!(user_languages, vec![(String::from("en"), 67), (String::from("de"), 33)]);
+/// ```
+pub fn parse_percentages(raw_languages: &str) -> Vec<(String, u8)> {
+    let languages: Vec<Language> = parse_languages(raw_languages)
+        .into_iter()
+        .filter(|l| l.quality > Quality::MIN)
+        .collect();
+    let total: u64 = languages
+        .iter()
+        .map(|l| u64::from(l.quality.as_millis()))
+        .sum();
+    let mut shares: Vec<(String, u64, u64)> = languages
+        .into_iter()
+        .map(|l| {
+            let share = u64::from(l.quality.as_millis()) * 100;
+            (l.name, share / total, share % total)
+        })
+        .collect();
+    let assigned: u64 = shares.iter().map(|(_, percentage, _)| percentage).sum();
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by_key(|&i| Reverse(shares[i].2));
+    for &i in by_remainder.iter().take((100 - assigned) as usize) {
+        shares[i].1 += 1;
+    }
+    shares
+        .into_iter()
+        .map(|(name, percentage, _)| (name, percentage as u8))
+        .collect()
+}
+
+/// Check whether two raw Accept-Language header values express the same preferences, so a
+/// cache can be keyed on them rather than on the raw bytes. Whitespace, casing, duplicates and
+/// the exact qualities don't matter, only the order of the languages and which of them share a
+/// quality. Languages with a quality of `0` have to be rejected in both.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::equivalent;
+///
+/// assert!(equivalent("en, de;q=0.5", "en , de ;q=0.3"));
+/// assert!(!equivalent("en, de;q=0.5", "de, en"));
+/// ```
+pub fn equivalent(a: &str, b: &str) -> bool {
+    fn preferences(raw_languages: &str) -> Vec<(String, Option<usize>)> {
+        let mut rank = 0;
+        let mut previous = None;
+        parse_unique_languages(raw_languages)
+            .into_iter()
+            .map(|l| {
+                if previous.is_some_and(|q| q != l.quality) {
+                    rank += 1;
+                }
+                previous = Some(l.quality);
+                let rank = if l.quality == Quality::MIN {
+                    None
+                } else {
+                    Some(rank)
+                };
+                (l.name.to_ascii_lowercase(), rank)
+            })
+            .collect()
+    }
+    preferences(a) == preferences(b)
+}
+
+/// Drop the qualities from languages like the ones returned by
+/// [`parse_with_quality`](parse_with_quality), keeping their order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{parse_with_quality, tags_only};
+///
+/// let user_languages = parse_with_quality("en-US, de;q=0.5, fr;q=0.1");
+/// let user_languages: Vec<_> = user_languages.into_iter().filter(|l| l.1 > 0.2).collect();
+/// assert_eq!(tags_only(&user_languages), vec!["en-US", "de"]);
+/// ```
+pub fn tags_only(languages: &[(String, f32)]) -> Vec<String> {
+    languages.iter().map(|(tag, _)| tag.clone()).collect()
+}
+
+/// Similar to [`tags_only`](tags_only) but borrowing the tags instead of copying them.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{parse_with_quality, tag_refs};
+///
+/// let user_languages = parse_with_quality("en-US, de;q=0.5");
+/// assert_eq!(tag_refs(&user_languages), vec!["en-US", "de"]);
+/// ```
+pub fn tag_refs(languages: &[(String, f32)]) -> Vec<&str> {
+    languages.iter().map(|(tag, _)| tag.as_str()).collect()
+}
+
+/// Compare the languages two headers accept, to debug why two clients get different content.
+/// The first list has the languages only `a` accepts and the second those only `b` accepts, each
+/// in its header's quality order. Qualities are otherwise ignored, except that a language with a
+/// quality of `0` isn't accepted. Tags are cased like
+/// [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) recommends before comparing.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::header_diff;
+///
+/// assert_eq!(header_diff("en, de", "en, fr"), (vec![String::from("de")], vec![String::from("fr")]));
+/// ```
+pub fn header_diff(a: &str, b: &str) -> (Vec<String>, Vec<String>) {
+    let accepted = |raw_languages: &str| {
+        dedup_languages(
+            parse_languages(raw_languages)
+                .into_iter()
+                .filter(|l| l.quality > Quality::MIN)
+                .map(|l| canonical_case(&l.name)),
+        )
+    };
+    let (a, b) = (accepted(a), accepted(b));
+    let only_a = a.iter().filter(|l| !b.contains(l)).cloned().collect();
+    let only_b = b.into_iter().filter(|l| !a.contains(l)).collect();
+    (only_a, only_b)
+}
+
+/// Keep the languages whose tag satisfies `predicate`, like languages your CDN has caches for,
+/// without changing their order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{parse_with_quality, retain_languages};
+///
+/// let user_languages = parse_with_quality("en-US, de;q=0.5, fr;q=0.1");
+/// let cached = retain_languages(user_languages, |tag| tag != "de");
+/// assert_eq!(cached, vec![(String::from("en-US"), 1.0), (String::from("fr"), 0.1)]);
+/// ```
+pub fn retain_languages(
+    mut languages: Vec<(String, f32)>,
+    predicate: impl Fn(&str) -> bool,
+) -> Vec<(String, f32)> {
+    languages.retain(|(tag, _)| predicate(tag));
+    languages
+}
+
+/// Similar to [`retain_languages`](retain_languages) but borrowing the tags instead of taking
+/// the languages.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{filter_languages, parse_with_quality};
+///
+/// let user_languages = parse_with_quality("en-US, de;q=0.5, fr;q=0.1");
+/// assert_eq!(filter_languages(&user_languages, |tag| tag != "de"), vec![("en-US", 1.0), ("fr", 0.1)]);
+/// ```
+pub fn filter_languages(
+    languages: &[(String, f32)],
+    predicate: impl Fn(&str) -> bool,
+) -> Vec<(&str, f32)> {
+    languages
+        .iter()
+        .filter(|(tag, _)| predicate(tag))
+        .map(|(tag, quality)| (tag.as_str(), *quality))
+        .collect()
+}
+
+/// The ordering [`parse_with_quality`](parse_with_quality) sorts languages by, for collections
+/// built elsewhere: descending quality, compared in the thousandths allowed by the spec. Languages
+/// with the same quality are equal, so a stable sort keeps their order.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::compare_languages;
+/// use std::cmp::Ordering;
+///
+/// let en = (String::from("en"), 1.0);
+/// let de = (String::from("de"), 0.5);
+/// assert_eq!(compare_languages(&en, &de), Ordering::Less);
+/// ```
+pub fn compare_languages(a: &(String, f32), b: &(String, f32)) -> Ordering {
+    quality_to_millis(b.1).cmp(&quality_to_millis(a.1))
+}
+
+/// Sort languages the same way [`parse_with_quality`](parse_with_quality) does, using
+/// [`compare_languages`](compare_languages).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::sort_languages;
+///
+/// let mut languages = vec![(String::from("de"), 0.5), (String::from("en"), 1.0)];
+/// sort_languages(&mut languages);
+/// assert_eq!(languages, vec![(String::from("en"), 1.0), (String::from("de"), 0.5)]);
+/// ```
+pub fn sort_languages(languages: &mut [(String, f32)]) {
+    languages.sort_by(compare_languages);
+}
+
+/// Compare an Accept-Language header value with your application's supported languages to find
+/// the common languages that could be presented to a user.
+///
+/// A supported language ending in `-*` matches every user language with that prefix: `en-*`
+/// matches `en-US` and `en-GB`. The user's language is returned, not the pattern. A language
+/// given more than once is only returned once, where its highest quality puts it.
+///
+/// The user's `*` range is ignored; see
+/// [`intersection_with_wildcard`](intersection_with_wildcard).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection;
+///
+/// let common_languages = intersection("en-US, en-GB;q=0.5", &["en-US", "de", "en-GB"]);
+/// let regional_languages = intersection("en-US, en-GB;q=0.5", &["en-*"]);
+/// assert_eq!(regional_languages, vec!["en-US", "en-GB"]);
+/// ```
+pub fn intersection(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    let user_languages = parse(raw_languages);
+    dedup_languages(
+        user_languages
+            .into_iter()
+            .filter(|l| is_supported(l, supported_languages)),
+    )
+}
+/// Order the supported languages for a language switcher: those the user accepts come first, in
+/// the order of their quality, followed by the rest in alphabetical order. Languages are
+/// compared ignoring case, languages with a quality of `0` aren't considered accepted, and
+/// every supported language is listed once.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::switcher_order;
+///
+/// assert_eq!(switcher_order("fr, en;q=0.5", &["de", "en", "fr"]), vec!["fr", "en", "de"]);
+/// ```
+pub fn switcher_order(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    let mut menu: Vec<&str> = Vec::with_capacity(supported_languages.len());
+    for language in parse_languages(raw_languages) {
+        if language.quality == Quality::MIN {
+            continue;
+        }
+        if let Some(supported) = find_supported(&language.name, supported_languages) {
+            if !menu.iter().any(|l| l.eq_ignore_ascii_case(supported)) {
+                menu.push(supported);
+            }
+        }
+    }
+    let mut rest: Vec<&str> = Vec::new();
+    for &language in supported_languages {
+        if !menu
+            .iter()
+            .chain(rest.iter())
+            .any(|l| l.eq_ignore_ascii_case(language))
+        {
+            rest.push(language);
+        }
+    }
+    rest.sort_by_key(|l| l.to_ascii_lowercase());
+    menu.into_iter().chain(rest).map(str::to_string).collect()
+}
+
+/// The number of languages [`intersection`](intersection) would return, counted without
+/// allocating, to size a buffer or skip negotiating when nothing matches. Like there, spaces
+/// inside a tag are removed, which is the only case that copies it.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_count;
+///
+/// assert_eq!(intersection_count("en-US, en-GB;q=0.5, en-US;q=0.1", &["en-US", "en-GB"]), 2);
+/// assert_eq!(intersection_count("fr", &["en-US", "en-GB"]), 0);
+/// ```
+pub fn intersection_count(raw_languages: &str, supported_languages: &[&str]) -> usize {
+    scan_stripped_languages(raw_languages)
+        .enumerate()
+        .filter(|(i, (name, _))| {
+            is_supported(name, supported_languages)
+                && !scan_stripped_languages(raw_languages)
+                    .take(*i)
+                    .any(|(other, _)| other == *name)
+        })
+        .count()
+}
+
+/// A key for caching responses by the negotiated languages instead of the raw header: a hash of
+/// the [`intersection`](intersection), in order and cased like
+/// [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) recommends. Headers that
+/// negotiate to the same languages get the same key. The hash is 64-bit FNV-1a, so keys are the
+/// same across runs and builds, but it isn't meant to resist collisions crafted on purpose.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::negotiation_key;
+///
+/// let supported = &["de", "en"];
+/// assert_eq!(negotiation_key("en , de", supported), negotiation_key("en,de;q=1", supported));
+/// assert_ne!(negotiation_key("en, de", supported), negotiation_key("de, en", supported));
+/// ```
+pub fn negotiation_key(raw_languages: &str, supported_languages: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for language in intersection(raw_languages, supported_languages) {
+        // The separator can't appear in a tag, so `a-b` and `a`, `b` hash differently.
+        for byte in canonical_case(&language).bytes().chain(Some(b',')) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Check whether supported languages can be passed to the `_ordered` functions like
+/// [`intersection_ordered`](intersection_ordered): they're sorted in the byte order of [`str`]
+/// and cased like [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) recommends,
+/// which is how browsers send them, since the `_ordered` functions compare exactly. Use
+/// [`sort_supported`](sort_supported) to fix a list that isn't.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::is_valid_ordered;
+///
+/// assert!(is_valid_ordered(&["de", "en-GB", "en-US"]));
+/// assert!(!is_valid_ordered(&["en-US", "de"]));
+/// assert!(!is_valid_ordered(&["de", "en-us"]));
+/// ```
+pub fn is_valid_ordered(supported_languages: &[&str]) -> bool {
+    supported_languages.windows(2).all(|w| w[0] <= w[1])
+        && supported_languages.iter().all(|l| is_canonical_case(l))
+}
+
+/// Prepare supported languages for the `_ordered` functions: each one is cased like
+/// [BCP 47](https://www.rfc-editor.org/rfc/rfc5646#section-2.1.1) recommends, then they're sorted
+/// and duplicates are removed, so the result passes [`is_valid_ordered`](is_valid_ordered).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::sort_supported;
+///
+/// assert_eq!(sort_supported(&["en-us", "de", "en-US"]), vec!["de", "en-US"]);
+/// ```
+pub fn sort_supported(supported_languages: &[&str]) -> Vec<String> {
+    let mut sorted: Vec<String> = supported_languages
+        .iter()
+        .map(|l| canonical_case(l))
+        .collect();
+    sorted.sort();
+    sorted.dedup();
+    sorted
+}
+
+/// Similar to [`intersection`](intersection) but using binary sort. The supported languages
+/// MUST be in alphabetical order, to find the common languages that could be presented
+/// to a user. Executes roughly 25% faster. Supported languages ending in `-*` aren't expanded
+/// and only match themselves. Like [`intersection`](intersection), each language is only
+/// returned once.
+///
+/// # Panics
+///
+/// The order is the byte order of [`str`], so uppercase letters sort before lowercase ones
+/// (`en-US` before `en-us`). Unsorted supported languages give wrong results in release builds
+/// and panic in debug builds.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_ordered;
+///
+/// let common_languages = intersection_ordered("en-US, en-GB;q=0.5", &["de", "en-GB", "en-US"]);
+/// ```
+pub fn intersection_ordered(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    debug_assert_sorted(supported_languages);
+    let user_languages = parse(raw_languages);
+    dedup_languages(
+        user_languages
+            .into_iter()
+            .filter(|l| supported_languages.binary_search(&l.as_str()).is_ok()),
+    )
+}
+/// Similar to [`intersection`](intersection) but with the quality as `f32` appended for each language.
+/// This enables distinction between the default language of a user (value 1.0) and the
+/// best match. If you don't want to assign your users immediatly to a non-default choice and you plan to add
+/// more languages later on in your webserver.
+///
+/// The user's `*` range is ignored; see
+/// [`intersection_with_quality_wildcard`](intersection_with_quality_wildcard).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_with_quality;
+///
+/// let common_languages = intersection_with_quality("en-US, en-GB;q=0.5", &["en-US", "de", "en-GB"]);
+/// assert_eq!(common_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// ```
+pub fn intersection_with_quality(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> Vec<(String, f32)> {
+    let user_languages = parse_with_quality(raw_languages);
+    user_languages
+        .into_iter()
+        .filter(|l| is_supported(&l.0, supported_languages))
+        .collect()
+}
+
+/// Similar to [`intersection_with_quality`](intersection_with_quality) but the common languages
+/// are grouped by quality, highest first, for loaders that treat languages of the same quality
+/// as interchangeable. Each tier lists its languages in header order. Languages with a quality
+/// of `0` are rejected, so they're left out.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_tiers;
+///
+/// let tiers = intersection_tiers("en-US, en-GB, de;q=0.5", &["en-US", "en-GB", "de"]);
+/// assert_eq!(
+///     tiers,
+///     vec![
+///         (1.0, vec![String::from("en-US"), String::from("en-GB")]),
+///         (0.5, vec![String::from("de")])
+///     ]
+/// );
+/// ```
+pub fn intersection_tiers(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> Vec<(f32, Vec<String>)> {
+    let mut tiers: Vec<(f32, Vec<String>)> = Vec::new();
+    for (language, quality) in intersection_with_quality(raw_languages, supported_languages) {
+        if quality == 0.0 {
+            continue;
+        }
+        match tiers.last_mut() {
+            Some((tier_quality, languages)) if *tier_quality == quality => languages.push(language),
+            _ => tiers.push((quality, vec![language])),
+        }
+    }
+    tiers
+}
+
+/// Similar to [`intersection_with_quality`](intersection_with_quality), for servers that rank
+/// their own languages too, e.g. because some translations are machine-made. Each supported
+/// language comes with a server quality, and a common language gets the lower of the user's and
+/// the server's quality, so a weak translation can't rank above what the server thinks it's
+/// worth. Languages are compared ignoring case, and the result is sorted by that combined
+/// quality, keeping the order of the header for ties. Languages combining to `0` are left out.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_min_quality;
+///
+/// let common_languages = intersection_min_quality("de, en;q=0.8", &[("en", 1.0), ("de", 0.5)]);
+/// assert_eq!(common_languages, vec![(String::from("en"), 0.8), (String::from("de"), 0.5)]);
+/// ```
+pub fn intersection_min_quality(
+    raw_languages: &str,
+    supported: &[(&str, f32)],
+) -> Vec<(String, f32)> {
+    let mut common_languages: Vec<(String, Quality)> = parse_unique_languages(raw_languages)
+        .into_iter()
+        .filter_map(|l| {
+            let &(_, server_quality) = supported
+                .iter()
+                .find(|(language, _)| language.eq_ignore_ascii_case(&l.name))?;
+            let quality = l.quality.min(Quality(quality_to_millis(server_quality)));
+            (quality > Quality::MIN).then_some((l.name, quality))
+        })
+        .collect();
+    common_languages.sort_by_key(|&(_, quality)| Reverse(quality));
+    common_languages
+        .into_iter()
+        .map(|(language, quality)| (language, quality.as_f32()))
+        .collect()
+}
+
+/// Similar to [`intersection_with_quality`](intersection_with_quality). The supported languages MUST
+/// be in alphabetical order, to find the common languages that could be presented to a user.
+/// Executes roughly 25% faster. Like [`intersection_ordered`](intersection_ordered), it doesn't
+/// expand supported languages ending in `-*`.
+///
+/// # Panics
+///
+/// Unsorted supported languages panic in debug builds, see
+/// [`intersection_ordered`](intersection_ordered#panics).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_ordered_with_quality;
+///
+/// let common_languages = intersection_ordered_with_quality("en-US, en-GB;q=0.5", &["de", "en-GB", "en-US"]);
+/// assert_eq!(common_languages,vec![(String::from("en-US"), 1.0), (String::from("en-GB"), 0.5)])
+/// ```
+pub fn intersection_ordered_with_quality(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> Vec<(String, f32)> {
+    debug_assert_sorted(supported_languages);
+    let user_languages = parse_with_quality(raw_languages);
+    user_languages
+        .into_iter()
+        .filter(|l| supported_languages.binary_search(&l.0.as_str()).is_ok())
+        .collect()
+}
+
+/// Similar to [`intersection`](intersection) but also returns the languages the user accepts that
+/// aren't supported, in quality order. This helps with prompts like "we don't support your
+/// preferred language yet". Languages rejected with a quality of `0` are in neither list, and a
+/// language given more than once is only listed once.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_with_missing;
+///
+/// let (common_languages, missing_languages) =
+///     intersection_with_missing("nl, en-US;q=0.5", &["en-US", "de"]);
+/// assert_eq!(common_languages, vec!["en-US"]);
+/// assert_eq!(missing_languages, vec!["nl"]);
+/// ```
+pub fn intersection_with_missing(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> (Vec<String>, Vec<String>) {
+    let (common_languages, missing_languages): (Vec<Language>, Vec<Language>) =
+        parse_languages(raw_languages)
+            .into_iter()
+            .filter(|l| l.quality > Quality::MIN)
+            .partition(|l| is_supported(&l.name, supported_languages));
+    (
+        dedup_languages(common_languages.into_iter().map(|l| l.name)),
+        dedup_languages(missing_languages.into_iter().map(|l| l.name)),
+    )
+}
+
+/// Which supported languages the user's `*` range matches in
+/// [`intersection_with_wildcard`](intersection_with_wildcard) and its quality and lossy variants.
+/// The other `intersection` functions ignore the `*` range, as if it was `Wildcard::Ignore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wildcard<'a> {
+    /// `*` matches nothing, like in [`intersection`](intersection).
+    Ignore,
+    /// `*` matches every supported language.
+    All,
+    /// `*` matches the supported languages in this list, like the server's default language.
+    Only(&'a [&'a str]),
+}
+
+/// Similar to [`intersection`](intersection) but the user's `*` range, as in
+/// `en-US, *;q=0.5`, matches supported languages too, as chosen by `wildcard`. They're returned
+/// where the `*` is in quality order, in the order they're supported, except for those matched
+/// before and supported languages ending in `-*`. Languages with a quality of `0`, including the
+/// `*` itself, are rejected, so they're never returned.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{intersection_with_wildcard, Wildcard};
+///
+/// let supported = &["de", "en-US", "fr"];
+/// let common_languages = intersection_with_wildcard("fr, *;q=0.5, de;q=0", supported, Wildcard::All);
+/// assert_eq!(common_languages, vec!["fr", "en-US"]);
+/// let common_languages =
+///     intersection_with_wildcard("it, *;q=0.5", supported, Wildcard::Only(&["en-US"]));
+/// assert_eq!(common_languages, vec!["en-US"]);
+/// ```
+pub fn intersection_with_wildcard(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    wildcard: Wildcard,
+) -> Vec<String> {
+    wildcard_intersection(
+        &parse_languages(raw_languages),
+        supported_languages,
+        wildcard,
+        false,
+    )
+    .into_iter()
+    .map(|(language, _)| language)
+    .collect()
+}
+
+/// Similar to [`intersection_with_wildcard`](intersection_with_wildcard) but with the quality as
+/// `f32` appended for each language, like
+/// [`intersection_with_quality`](intersection_with_quality). Languages matched by the `*` get
+/// its quality.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{intersection_with_quality_wildcard, Wildcard};
+///
+/// let common_languages =
+///     intersection_with_quality_wildcard("fr, *;q=0.5", &["de", "fr"], Wildcard::All);
+/// assert_eq!(common_languages, vec![(String::from("fr"), 1.0), (String::from("de"), 0.5)]);
+/// ```
+pub fn intersection_with_quality_wildcard(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    wildcard: Wildcard,
+) -> Vec<(String, f32)> {
+    wildcard_intersection(
+        &parse_languages(raw_languages),
+        supported_languages,
+        wildcard,
+        false,
+    )
+    .into_iter()
+    .map(|(language, quality)| (language, quality.as_f32()))
+    .collect()
+}
+
+/// Similar to [`intersection_with_wildcard`](intersection_with_wildcard) but lossy like
+/// [`intersection_lossy`](intersection_lossy): the other user languages fall back to the script
+/// implied by their region or to their base language, and the supported languages are returned.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{intersection_lossy_wildcard, Wildcard};
+///
+/// let common_languages =
+///     intersection_lossy_wildcard("fr-CH, *;q=0.5", &["de", "fr"], Wildcard::All);
+/// assert_eq!(common_languages, vec!["fr", "de"]);
+/// ```
+pub fn intersection_lossy_wildcard(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    wildcard: Wildcard,
+) -> Vec<String> {
+    wildcard_intersection(
+        &parse_languages(raw_languages),
+        supported_languages,
+        wildcard,
+        true,
+    )
+    .into_iter()
+    .map(|(language, _)| language)
+    .collect()
+}
+
+fn wildcard_intersection(
+    user_languages: &[Language],
+    supported_languages: &[&str],
+    wildcard: Wildcard,
+    lossy: bool,
+) -> Vec<(String, Quality)> {
+    let is_rejected = |tag: &str| {
+        user_languages
+            .iter()
+            .any(|l| l.quality == Quality::MIN && l.name.eq_ignore_ascii_case(tag))
+    };
+    let mut common_languages: Vec<(String, Quality)> = Vec::new();
+    for language in user_languages {
+        if language.quality == Quality::MIN {
+            continue;
+        }
+        if language.name != "*" {
+            let supported = if lossy {
+                find_supported(&language.name, supported_languages).or_else(|| {
+                    find_supported_fallback(&language.name, supported_languages)
+                        .filter(|l| !is_rejected(l))
+                })
+            } else {
+                Some(language.name.as_str()).filter(|l| is_supported(l, supported_languages))
+            };
+            if let Some(supported) = supported {
+                if !common_languages.iter().any(|(l, _)| l == supported) {
+                    common_languages.push((supported.to_string(), language.quality));
+                }
+            }
+            continue;
+        }
+        let expanded = supported_languages.iter().filter(|&&l| match wildcard {
+            Wildcard::Ignore => false,
+            Wildcard::All => true,
+            Wildcard::Only(languages) => languages.iter().any(|o| o.eq_ignore_ascii_case(l)),
+        });
+        for &supported in expanded {
+            if !supported.ends_with("-*")
+                && !is_rejected(supported)
+                && !common_languages
+                    .iter()
+                    .any(|(l, _)| l.eq_ignore_ascii_case(supported))
+            {
+                common_languages.push((supported.to_string(), language.quality));
+            }
+        }
+    }
+    common_languages
+}
+
+/// Similar to [`intersection`](intersection) but stops after the first `n` common languages, and
+/// lists each one only once, e.g. for a page shown in two languages at the same time.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::top_n;
+///
+/// let common_languages = top_n("en-US, de;q=0.5, fr;q=0.1", &["fr", "de", "en-US"], 2);
+/// assert_eq!(common_languages, vec!["en-US", "de"]);
+/// ```
+pub fn top_n(raw_languages: &str, supported_languages: &[&str], n: usize) -> Vec<String> {
+    let mut common_languages: Vec<String> = Vec::with_capacity(n.min(supported_languages.len()));
+    for language in parse_languages(raw_languages) {
+        if common_languages.len() == n {
+            break;
+        }
+        if is_supported(&language.name, supported_languages)
+            && !common_languages.contains(&language.name)
+        {
+            common_languages.push(language.name);
+        }
+    }
+    common_languages
+}
+
+/// Similar to [`intersection`](intersection) but also returns the supported languages that none
+/// of the user languages matched, in the order they were given. This is the opposite of
+/// [`intersection_with_missing`](intersection_with_missing), e.g. for suggesting languages a user
+/// could add.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_and_unused;
+///
+/// let (common_languages, unused_languages) =
+///     intersection_and_unused("en, de", &["en", "de", "fr"]);
+/// assert_eq!(common_languages, vec!["en", "de"]);
+/// assert_eq!(unused_languages, vec!["fr"]);
+/// ```
+pub fn intersection_and_unused(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> (Vec<String>, Vec<String>) {
+    let common_languages = intersection(raw_languages, supported_languages);
+    let unused_languages = supported_languages
+        .iter()
+        .filter(|&&supported| {
+            !common_languages
+                .iter()
+                .any(|l| is_supported(l, &[supported]))
+        })
+        .map(|l| l.to_string())
+        .collect();
+    (common_languages, unused_languages)
+}
+
+/// Similar to [`intersection`](intersection) but only keeps the languages the user gave the
+/// default quality of `1`, explicitly or not, in header order. Anything the user rated lower is
+/// left out, however close to `1` it is. A language given more than once is only returned once.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_defaults_only;
+///
+/// let common_languages = intersection_defaults_only("nl, en-US;q=0.7", &["nl", "en-US"]);
+/// assert_eq!(common_languages, vec!["nl"]);
+/// ```
+pub fn intersection_defaults_only(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> Vec<String> {
+    dedup_languages(
+        parse_languages(raw_languages)
+            .into_iter()
+            .filter(|l| l.quality == Quality::MAX && is_supported(&l.name, supported_languages))
+            .map(|l| l.name),
+    )
+}
+
+/// Similar to [`parse_with_quality`](parse_with_quality) but without allocating, for embedded
+/// uses. The `N` languages with the highest quality are written into a fixed-size array as
+/// borrowed tags with their quality in thousandths, sorted the same way, and the number of
+/// languages written is returned alongside. The languages that don't fit are ignored.
+///
+/// Like [`parse_cow`](parse_cow), whitespace is trimmed around each tag and quality rather than
+/// removed everywhere, and folded header lines aren't unfolded.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_fixed;
+///
+/// let (user_languages, len) = parse_fixed::<2>("de;q=0.5, en-US, fr;q=0.1");
+/// assert_eq!(len, 2);
+/// assert_eq!(user_languages, [Some(("en-US", 1000)), Some(("de", 500))]);
+/// ```
+pub fn parse_fixed<const N: usize>(raw_languages: &str) -> ([Option<(&str, u16)>; N], usize) {
+    let mut languages: [Option<(&str, u16)>; N] = [None; N];
+    let mut len = 0;
+    for (name, quality) in scan_languages(raw_languages) {
+        let quality = quality.as_millis();
+        let position = languages[..len]
+            .iter()
+            .position(|l| l.is_some_and(|(_, q)| q < quality))
+            .unwrap_or(len);
+        if position == N {
+            continue;
+        }
+        len = (len + 1).min(N);
+        languages[position..len].rotate_right(1);
+        languages[position] = Some((name, quality));
+    }
+    (languages, len)
+}
+
+/// Similar to [`parse`](parse) but for a header that may be absent, returning an empty list
+/// for `None`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_opt;
+///
+/// assert_eq!(parse_opt(Some("en-US, en-GB;q=0.5")), vec!["en-US", "en-GB"]);
+/// assert!(parse_opt(None).is_empty());
+/// ```
+pub fn parse_opt(raw_languages: Option<&str>) -> Vec<String> {
+    raw_languages.map(parse).unwrap_or_default()
+}
+
+/// Similar to [`parse`](parse) but for the raw bytes of a header value, returning an empty list
+/// when they aren't valid UTF-8.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::parse_bytes;
+///
+/// assert_eq!(parse_bytes(b"en-US, en-GB;q=0.5"), vec!["en-US", "en-GB"]);
+/// assert!(parse_bytes(b"en-US, \xff").is_empty());
+/// ```
+pub fn parse_bytes(raw_languages: &[u8]) -> Vec<String> {
+    str::from_utf8(raw_languages).map(parse).unwrap_or_default()
+}
+
+/// Similar to [`intersection`](intersection) but for a header that may be absent, returning an
+/// empty list for `None`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_opt;
+///
+/// let common_languages = intersection_opt(Some("en-US, en-GB;q=0.5"), &["en-US", "de", "en-GB"]);
+/// assert!(intersection_opt(None, &["en-US", "de", "en-GB"]).is_empty());
+/// ```
+pub fn intersection_opt(raw_languages: Option<&str>, supported_languages: &[&str]) -> Vec<String> {
+    raw_languages
+        .map(|l| intersection(l, supported_languages))
+        .unwrap_or_default()
+}
+
+/// The outcome of [`negotiate_status`](negotiate_status).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Negotiation {
+    /// There's no Accept-Language header, so other signals like the location can be used.
+    NoHeader,
+    /// There's a header, but none of its languages are supported, which could be a
+    /// `406 Not Acceptable`.
+    NoMatch,
+    /// The [`intersection`](intersection) of the header and the supported languages, which is
+    /// never empty.
+    Matched(Vec<String>),
+}
+
+/// Similar to [`intersection_opt`](intersection_opt) but tells a missing header apart from a
+/// header without any supported languages. A header that is present but empty is
+/// [`Negotiation::NoMatch`].
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{negotiate_status, Negotiation};
+///
+/// assert_eq!(negotiate_status(None, &["en"]), Negotiation::NoHeader);
+/// assert_eq!(negotiate_status(Some("de"), &["en"]), Negotiation::NoMatch);
+/// assert_eq!(
+///     negotiate_status(Some("de, en;q=0.5"), &["en"]),
+///     Negotiation::Matched(vec![String::from("en")])
+/// );
+/// ```
+pub fn negotiate_status(raw_languages: Option<&str>, supported_languages: &[&str]) -> Negotiation {
+    match raw_languages.map(|l| intersection(l, supported_languages)) {
+        None => Negotiation::NoHeader,
+        Some(common_languages) if common_languages.is_empty() => Negotiation::NoMatch,
+        Some(common_languages) => Negotiation::Matched(common_languages),
+    }
+}
+
+/// Pick the single language to serve: the supported language matching the user's most preferred
+/// language, compared case-insensitively, or `default` when nothing matches. Languages the user
+/// rejected with a quality of `0` are never picked. The returned tag is the supported entry, so
+/// it keeps your casing.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::select;
+///
+/// assert_eq!(select("en-US, de;q=0.5", &["de", "en-us"], "fr"), "en-us");
+/// assert_eq!(select("en-US, de;q=0.5", &["fr"], "fr"), "fr");
+/// ```
+pub fn select(raw_languages: &str, supported_languages: &[&str], default: &str) -> String {
+    select_from(
+        &parse_languages(raw_languages),
+        supported_languages,
+        default,
+    )
+}
+
+fn select_from(user_languages: &[Language], supported_languages: &[&str], default: &str) -> String {
+    user_languages
+        .iter()
+        .filter(|l| l.quality > Quality::MIN)
+        .find_map(|l| find_supported(&l.name, supported_languages))
+        .unwrap_or(default)
+        .to_string()
+}
+
+/// Similar to [`select`](select) but with several sets of supported languages tried in order,
+/// like a tenant's languages before the global ones. The best match in the first set that has
+/// one wins, even if a later set has a language the user prefers; if no set matches, the
+/// default is returned. The header is only parsed once.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::negotiate_layered;
+///
+/// let tenant: &[&str] = &["fr"];
+/// let global: &[&str] = &["en", "de"];
+/// assert_eq!(negotiate_layered("de, fr;q=0.5", &[tenant, global], "en"), "fr");
+/// assert_eq!(negotiate_layered("de", &[tenant, global], "en"), "de");
+/// ```
+pub fn negotiate_layered(raw_languages: &str, sets: &[&[&str]], default: &str) -> String {
+    let user_languages = parse_languages(raw_languages);
+    sets.iter()
+        .find_map(|supported_languages| {
+            user_languages
+                .iter()
+                .filter(|l| l.quality > Quality::MIN)
+                .find_map(|l| find_supported(&l.name, supported_languages))
+        })
+        .unwrap_or(default)
+        .to_string()
+}
+
+/// Similar to [`select`](select) but also returns the quality the user gave the chosen language,
+/// to log how confident the choice was. The default is returned with a quality of `0`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::select_detailed;
+///
+/// assert_eq!(select_detailed("en-US, de;q=0.5", &["de"], "fr"), (String::from("de"), 0.5));
+/// assert_eq!(select_detailed("en-US", &["de"], "fr"), (String::from("fr"), 0.0));
+/// ```
+pub fn select_detailed(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    default: &str,
+) -> (String, f32) {
+    parse_languages(raw_languages)
+        .iter()
+        .filter(|l| l.quality > Quality::MIN)
+        .find_map(|l| find_supported(&l.name, supported_languages).map(|s| (s, l.quality.as_f32())))
+        .map_or_else(
+            || (default.to_string(), 0.0),
+            |(supported, quality)| (supported.to_string(), quality),
+        )
+}
+
+/// Similar to [`select`](select) but falls back to the base language when no supported language
+/// matches exactly, so a user asking for `en-US` gets a supported `en`. The Chinese regions imply
+/// their script, so `zh-TW` gets a supported `zh-Hant`. The base language is never used when its
+/// default script is a different one, so `sr-Latn` doesn't get the Cyrillic `sr`. Exact matches always win
+/// over base-language matches, even when the exact match has a lower quality.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::select_lossy;
+///
+/// assert_eq!(select_lossy("en-US, de;q=0.5", &["fr", "en"], "fr"), "en");
+/// assert_eq!(select_lossy("en-US, de;q=0.5", &["de", "en"], "fr"), "de");
+/// ```
+pub fn select_lossy(raw_languages: &str, supported_languages: &[&str], default: &str) -> String {
+    negotiate_explained(raw_languages, supported_languages, default).0
+}
+
+/// Pick the supported language that both sides like best, when the server ranks its languages
+/// too, e.g. because some translations are incomplete. Each supported language scores the product
+/// of its server quality and the quality the user gives it, ignoring case, or the quality of the
+/// user's `*` when it isn't listed. The highest score above `0` wins; a tie goes to the higher
+/// user quality and then to the earlier supported language.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::negotiate_priorities;
+///
+/// let server = &[("en", 0.5), ("de", 1.0)];
+/// assert_eq!(negotiate_priorities("en, de;q=0.8", server), Some(String::from("de")));
+/// assert_eq!(negotiate_priorities("en, de;q=0.4", server), Some(String::from("en")));
+/// ```
+pub fn negotiate_priorities(raw_languages: &str, server: &[(&str, f32)]) -> Option<String> {
+    let user_languages = parse_unique_languages(raw_languages);
+    let wildcard = user_languages
+        .iter()
+        .find(|l| l.name == "*")
+        .map_or(Quality::MIN, |l| l.quality);
+    let mut best: Option<(u32, Quality, &str)> = None;
+    for &(language, server_quality) in server {
+        let user_quality = user_languages
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(language))
+            .map_or(wildcard, |l| l.quality);
+        let score =
+            u32::from(user_quality.as_millis()) * u32::from(quality_to_millis(server_quality));
+        if score > 0 && best.is_none_or(|(s, q, _)| (score, user_quality) > (s, q)) {
+            best = Some((score, user_quality, language));
+        }
+    }
+    best.map(|(_, _, language)| language.to_string())
+}
+
+/// Pick a supported language by blending the current header with a `prior` one stored from an
+/// earlier request, so the choice doesn't flip between sessions over a near tie. Each supported
+/// language scores
+///
+/// ```text
+/// (1 - prior_weight) * current quality + prior_weight * prior quality
+/// ```
+///
+/// where a quality is the one the header gives the language, ignoring case, or the quality of
+/// the header's `*` when it isn't listed, and `prior_weight` is clamped to `0..=1`. The highest
+/// score above `0` wins; a tie goes to the higher current quality and then to the earlier
+/// supported language.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::negotiate_with_prior;
+///
+/// let supported = &["en", "de"];
+/// assert_eq!(negotiate_with_prior("en, de;q=0.9", supported, "de", 0.2), Some(String::from("de")));
+/// assert_eq!(negotiate_with_prior("en, de;q=0.1", supported, "de", 0.2), Some(String::from("en")));
+/// ```
+pub fn negotiate_with_prior(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    prior: &str,
+    prior_weight: f32,
+) -> Option<String> {
+    let user_languages = parse_unique_languages(raw_languages);
+    let prior_languages = parse_unique_languages(prior);
+    let quality_of = |languages: &[Language], tag: &str| {
+        let wildcard = languages
+            .iter()
+            .find(|l| l.name == "*")
+            .map_or(Quality::MIN, |l| l.quality);
+        languages
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(tag))
+            .map_or(wildcard, |l| l.quality)
+    };
+    let prior_weight = u32::from(quality_to_millis(prior_weight));
+    let mut best: Option<(u32, Quality, &str)> = None;
+    for &language in supported_languages {
+        let user_quality = quality_of(&user_languages, language);
+        let prior_quality = quality_of(&prior_languages, language);
+        let score = (1000 - prior_weight) * u32::from(user_quality.as_millis())
+            + prior_weight * u32::from(prior_quality.as_millis());
+        if score > 0 && best.is_none_or(|(s, q, _)| (score, user_quality) > (s, q)) {
+            best = Some((score, user_quality, language));
+        }
+    }
+    best.map(|(_, _, language)| language.to_string())
+}
+
+/// Why [`negotiate_explained`](negotiate_explained) chose the language it returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NegotiationReason {
+    /// A supported language equals one of the user languages, ignoring case.
+    ExactMatch,
+    /// A supported language is the base language of one of the user languages, or the script
+    /// implied by its region.
+    LossyMatch,
+    /// Nothing matched, but the user accepts any language through `*`, so the default is fine.
+    Wildcard,
+    /// Nothing matched and the user doesn't accept any language, so the default was used anyway.
+    FellBackToDefault,
+}
+
+/// Similar to [`select_lossy`](select_lossy), and choosing the same language, but also returns
+/// why it was chosen, for metrics or logs.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::{negotiate_explained, NegotiationReason};
+///
+/// assert_eq!(
+///     negotiate_explained("en-US, de;q=0.5", &["fr", "en"], "fr"),
+///     (String::from("en"), NegotiationReason::LossyMatch)
+/// );
+/// assert_eq!(
+///     negotiate_explained("it, *;q=0.1", &["fr", "en"], "fr"),
+///     (String::from("fr"), NegotiationReason::Wildcard)
+/// );
+/// ```
+pub fn negotiate_explained(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    default: &str,
+) -> (String, NegotiationReason) {
+    let user_languages: Vec<Language> = parse_languages(raw_languages)
+        .into_iter()
+        .filter(|l| l.quality > Quality::MIN)
+        .collect();
+    if let Some(language) = user_languages
+        .iter()
+        .find_map(|l| find_supported(&l.name, supported_languages))
+    {
+        return (language.to_string(), NegotiationReason::ExactMatch);
+    }
+    if let Some(language) = user_languages
+        .iter()
+        .find_map(|l| find_supported_fallback(&l.name, supported_languages))
+    {
+        return (language.to_string(), NegotiationReason::LossyMatch);
+    }
+    let reason = if user_languages.iter().any(|l| l.name == "*") {
+        NegotiationReason::Wildcard
+    } else {
+        NegotiationReason::FellBackToDefault
+    };
+    (default.to_string(), reason)
+}
+
+/// Similar to [`intersection`](intersection) but lossy like [`select_lossy`](select_lossy): a user
+/// language without an exact supported match falls back to the script implied by its region for
+/// Chinese, or else to its base language, ignoring case.
+/// The supported languages are returned, in the order of the user languages they matched, and
+/// each one only once. Languages with a quality of `0` are skipped, and never used as the base
+/// language of another one either.
+///
+/// The user's `*` range is ignored; see
+/// [`intersection_lossy_wildcard`](intersection_lossy_wildcard).
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_lossy;
+///
+/// let common_languages = intersection_lossy("en-US, de-CH;q=0.5, de;q=0.3", &["de", "en-us"]);
+/// assert_eq!(common_languages, vec!["en-us", "de"]);
+/// ```
+pub fn intersection_lossy(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    intersection_lossy_flagged(raw_languages, supported_languages).0
+}
+
+/// Similar to [`intersection_lossy`](intersection_lossy) but also tells whether any of the
+/// common languages only matched by falling back, e.g. to show that a translation is
+/// approximate.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_lossy_flagged;
+///
+/// let (common_languages, lossy) = intersection_lossy_flagged("en-US, de", &["de", "en"]);
+/// assert_eq!(common_languages, vec!["en", "de"]);
+/// assert!(lossy);
+/// ```
+pub fn intersection_lossy_flagged(
+    raw_languages: &str,
+    supported_languages: &[&str],
+) -> (Vec<String>, bool) {
+    lossy_intersection(&parse_languages(raw_languages), supported_languages)
+}
+
+fn lossy_intersection(
+    user_languages: &[Language],
+    supported_languages: &[&str],
+) -> (Vec<String>, bool) {
+    let is_rejected = |tag: &str| {
+        user_languages
+            .iter()
+            .any(|l| l.quality == Quality::MIN && l.name.eq_ignore_ascii_case(tag))
+    };
+    let mut common_languages: Vec<String> = Vec::new();
+    let mut lossy = false;
+    for language in user_languages {
+        if language.quality == Quality::MIN {
+            continue;
+        }
+        let supported = find_supported(&language.name, supported_languages)
+            .map(|l| (l, false))
+            .or_else(|| {
+                find_supported_fallback(&language.name, supported_languages)
+                    .filter(|l| !is_rejected(l))
+                    .map(|l| (l, true))
+            });
+        if let Some((supported, fallback)) = supported {
+            if !common_languages.iter().any(|l| l == supported) {
+                common_languages.push(supported.to_string());
+                lossy |= fallback;
+            }
+        }
+    }
+    (common_languages, lossy)
+}
+
+/// Similar to [`intersection_lossy`](intersection_lossy) but every supported language that
+/// matches a user language is returned instead of only the best one: its exact match, the script
+/// implied by its region and its base language, in that order. `en-US` with the supported
+/// languages `en` and `en-US` gives both, since both serve the user.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::intersection_all_lossy;
+///
+/// let common_languages = intersection_all_lossy("en-US, de;q=0.5", &["de", "en", "en-US"]);
+/// assert_eq!(common_languages, vec!["en-US", "en", "de"]);
+/// ```
+pub fn intersection_all_lossy(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    let user_languages = parse_languages(raw_languages);
+    let is_rejected = |tag: &str| {
+        user_languages
+            .iter()
+            .any(|l| l.quality == Quality::MIN && l.name.eq_ignore_ascii_case(tag))
+    };
+    let mut common_languages: Vec<String> = Vec::new();
+    for language in &user_languages {
+        if language.quality == Quality::MIN {
+            continue;
+        }
+        let candidates = if is_private_use(&language.name) {
+            [Some(language.name.as_str()), None, None]
+        } else {
+            [
+                Some(language.name.as_str()),
+                implied_script(&language.name),
+                keeps_script(&language.name).then_some(primary_language(&language.name)),
+            ]
+        };
+        for (i, candidate) in candidates.iter().flatten().enumerate() {
+            let supported = match find_supported(candidate, supported_languages) {
+                Some(supported) if i == 0 || !is_rejected(supported) => supported,
+                _ => continue,
+            };
+            if !common_languages.iter().any(|l| l == supported) {
+                common_languages.push(supported.to_string());
+            }
+        }
+    }
+    common_languages
+}
+
+/// Turn the [`intersection_lossy`](intersection_lossy) of the user and supported languages into
+/// the file names of translation bundles to try, in order, like `messages.en-US.ftl`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::bundle_candidates;
+///
+/// let bundles = bundle_candidates("en-US, de-CH;q=0.5", &["de", "en-US"], "messages", "ftl");
+/// assert_eq!(bundles, vec!["messages.en-US.ftl", "messages.de.ftl"]);
+/// ```
+pub fn bundle_candidates(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    prefix: &str,
+    ext: &str,
+) -> Vec<String> {
+    intersection_lossy(raw_languages, supported_languages)
+        .into_iter()
+        .map(|l| format!("{}.{}.{}", prefix, l, ext))
+        .collect()
+}
+
+/// Check whether a language tag has a region, like `US` in `en-US` or `419` in `es-419`, as
+/// classified by [`LanguageTag::region`]. A script like `Hant` in `zh-Hant` isn't a region.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::is_region_specific;
+///
+/// assert!(is_region_specific("en-US"));
+/// assert!(!is_region_specific("zh-Hant"));
+/// ```
+pub fn is_region_specific(tag: &str) -> bool {
+    LanguageTag::parse(tag).region().is_some()
+}
+
+/// List the progressively less specific forms of a language tag, starting with the tag itself,
+/// as used by the Lookup scheme of [RFC 4647](https://www.rfc-editor.org/rfc/rfc4647#section-3.4).
+/// A single-letter subtag left at the end after truncating is removed as well, since it only
+/// introduces the subtags that followed it. A private use tag like `x-pig-latin` is never
+/// truncated, since there's no standard language to fall back to.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::fallback_chain;
+///
+/// assert_eq!(
+///     fallback_chain("zh-Hant-CN-x-private"),
+///     vec!["zh-Hant-CN-x-private", "zh-Hant-CN", "zh-Hant", "zh"]
+/// );
+/// ```
+pub fn fallback_chain(tag: &str) -> Vec<&str> {
+    if is_private_use(tag) {
+        return vec![tag];
+    }
+    let mut chain = Vec::new();
+    let mut end = tag.len();
+    while end > 0 {
+        chain.push(&tag[..end]);
+        end = tag[..end].rfind('-').unwrap_or(0);
+        let last_subtag_start = tag[..end].rfind('-').map_or(0, |i| i + 1);
+        if end - last_subtag_start == 1 {
+            end = last_subtag_start.saturating_sub(1);
+        }
+    }
+    chain
+}
+
+/// Plan which bundles to load: the language chosen by [`select_lossy`](select_lossy), followed
+/// by the supported languages in its [`fallback_chain`](fallback_chain), compared
+/// case-insensitively, and finally the default unless it's already in the list.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::negotiate_with_fallbacks;
+///
+/// let bundles = negotiate_with_fallbacks("fr-CA, de;q=0.5", &["en", "fr", "fr-CA"], "en");
+/// assert_eq!(bundles, vec!["fr-CA", "fr", "en"]);
+/// ```
+pub fn negotiate_with_fallbacks(
+    raw_languages: &str,
+    supported_languages: &[&str],
+    default: &str,
+) -> Vec<String> {
+    let best = select_lossy(raw_languages, supported_languages, default);
+    let mut bundles: Vec<String> = fallback_chain(&best)
+        .into_iter()
+        .skip(1)
+        .filter_map(|tag| find_supported(tag, supported_languages))
+        .map(str::to_string)
+        .collect();
+    bundles.insert(0, best);
+    if !bundles.iter().any(|l| l.eq_ignore_ascii_case(default)) {
+        bundles.push(default.to_string());
+    }
+    bundles
+}
+
+/// Expand every language the user accepts into its [`fallback_chain`](fallback_chain), in
+/// quality order, giving the full list of tags to try when loading translations. Each tag only
+/// appears once, at its first (most preferred) position, compared case-insensitively. The `*`
+/// range and languages with a quality of `0` aren't translations, so they're left out.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::search_path;
+///
+/// assert_eq!(search_path("fr-CA, en;q=0.8"), vec!["fr-CA", "fr", "en"]);
+/// ```
+pub fn search_path(raw_languages: &str) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut path: Vec<String> = Vec::new();
+    for language in parse_languages(raw_languages) {
+        if language.quality == Quality::MIN || language.name == "*" {
+            continue;
+        }
+        for tag in fallback_chain(&language.name) {
+            if seen.insert(tag.to_ascii_lowercase()) {
+                path.push(tag.to_string());
+            }
+        }
+    }
+    path
+}
+
+/// Filter the supported languages with the user's languages as ranges, by the Basic Filtering
+/// scheme of [RFC 4647](https://www.rfc-editor.org/rfc/rfc4647#section-3.3.1): a range matches
+/// the tags it equals or is a prefix of, ignoring case, so `de` matches `de`, `de-CH` and
+/// `de-DE-1996`, and `*` matches every tag. A private use range like `x-pig` only matches
+/// itself. The matching supported languages are returned in the quality order of the ranges
+/// that matched them, then in the order they're supported, each once. Ranges with a quality of
+/// `0` are skipped.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::basic_filter;
+///
+/// let supported = &["en-US", "de-DE-1996", "de-CH", "fr"];
+/// assert_eq!(basic_filter("de, en;q=0.5", supported), vec!["de-DE-1996", "de-CH", "en-US"]);
+/// ```
+pub fn basic_filter(raw_languages: &str, supported_languages: &[&str]) -> Vec<String> {
+    let mut common_languages: Vec<&str> = Vec::new();
+    for range in parse_languages(raw_languages) {
+        if range.quality == Quality::MIN {
+            continue;
+        }
+        for &language in supported_languages {
+            if basic_match(&range.name, language) && !common_languages.contains(&language) {
+                common_languages.push(language);
+            }
+        }
+    }
+    common_languages.into_iter().map(str::to_string).collect()
+}
+
+/// Find the single best supported language by the Lookup scheme of
+/// [RFC 4647](https://www.rfc-editor.org/rfc/rfc4647#section-3.4): each of the user's languages,
+/// in quality order, is truncated one subtag at a time along its
+/// [`fallback_chain`](fallback_chain) until it equals a supported language, ignoring case, so
+/// `zh-Hant-CN-x-private` tries `zh-Hant-CN`, `zh-Hant` and then `zh`. `None` is returned when
+/// nothing matches, for the caller to use its default. The `*` range and languages with a
+/// quality of `0` are skipped.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::lookup;
+///
+/// let supported = &["en", "zh-Hant", "zh"];
+/// assert_eq!(lookup("zh-Hant-CN-x-private, en;q=0.5", supported), Some(String::from("zh-Hant")));
+/// assert_eq!(lookup("fr-CA", supported), None);
+/// ```
+pub fn lookup(raw_languages: &str, supported_languages: &[&str]) -> Option<String> {
+    parse_languages(raw_languages)
+        .iter()
+        .filter(|l| l.quality > Quality::MIN && l.name != "*")
+        .find_map(|l| {
+            fallback_chain(&l.name)
+                .into_iter()
+                .find_map(|tag| find_supported(tag, supported_languages))
+        })
+        .map(str::to_string)
+}
+
+/// Find the most specific supported language for the user's most preferred language. Among the
+/// supported languages the preferred language matches as a prefix (by the Basic Filtering scheme
+/// of [RFC 4647](https://www.rfc-editor.org/rfc/rfc4647#section-3.3.1)), the one with the most
+/// subtags is returned, and the first one listed wins a tie. This suits highlighting the closest
+/// entry in a language menu.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::most_specific_match;
+///
+/// let language = most_specific_match("en, de;q=0.5", &["de", "en", "en-US"]);
+/// assert_eq!(language, Some(String::from("en-US")));
+/// ```
+pub fn most_specific_match(raw_languages: &str, supported_languages: &[&str]) -> Option<String> {
+    let preferred = parse_languages(raw_languages)
+        .into_iter()
+        .find(|l| l.quality > Quality::MIN)?;
+    let mut best: Option<&str> = None;
+    for &language in supported_languages {
+        if basic_match(&preferred.name, language)
+            && best.is_none_or(|b| subtag_count(language) > subtag_count(b))
+        {
+            best = Some(language);
+        }
+    }
+    best.map(str::to_string)
+}
+
+/// Similar to [`most_specific_match`](most_specific_match) but tolerant of typos, for
+/// preferences typed in by people rather than sent by browsers. When no supported language
+/// matches the user's preferred language, the supported language with the fewest subtags added,
+/// removed or replaced is returned, as long as that's at most [`CLOSEST_MAX_DISTANCE`]. The
+/// primary language counts as a subtag too, but it can only be replaced by one that's a single
+/// typo away, so `eb-US` finds `en-US` while `fr` never finds `de`. The first one wins a tie.
+/// Private use tags still only match exactly.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::closest;
+///
+/// assert_eq!(closest("en-UK", &["de", "en-GB"]), Some(String::from("en-GB")));
+/// assert_eq!(closest("eb-GB", &["de", "en-GB"]), Some(String::from("en-GB")));
+/// assert_eq!(closest("fr", &["de", "en-GB"]), None);
+/// ```
+pub fn closest(raw_languages: &str, supported_languages: &[&str]) -> Option<String> {
+    if let Some(language) = most_specific_match(raw_languages, supported_languages) {
+        return Some(language);
+    }
+    let preferred = parse_languages(raw_languages)
+        .into_iter()
+        .find(|l| l.quality > Quality::MIN)?;
+    if is_private_use(&preferred.name) {
+        return None;
+    }
+    let mut best: Option<(usize, &str)> = None;
+    for &language in supported_languages {
+        if !is_typo_of(
+            primary_language(language),
+            primary_language(&preferred.name),
+        ) {
+            continue;
+        }
+        let distance = subtag_distance(&preferred.name, language);
+        if distance <= CLOSEST_MAX_DISTANCE && best.is_none_or(|(d, _)| distance < d) {
+            best = Some((distance, language));
+        }
+    }
+    best.map(|(_, language)| language.to_string())
+}
+
+/// The largest number of subtag edits [`closest`](closest) accepts.
+pub const CLOSEST_MAX_DISTANCE: usize = 2;
+
+/// Check whether two subtags are at most one character added, removed or replaced apart,
+/// ignoring case, like `en` and `eb` or `de` and `deu`.
+fn is_typo_of(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (longer, shorter) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let equal = |x: &[u8], y: &[u8]| x.eq_ignore_ascii_case(y);
+    let first_difference = shorter
+        .iter()
+        .zip(longer)
+        .position(|(x, y)| !x.eq_ignore_ascii_case(y))
+        .unwrap_or(shorter.len());
+    match longer.len() - shorter.len() {
+        0 => {
+            first_difference == shorter.len()
+                || equal(
+                    &longer[first_difference + 1..],
+                    &shorter[first_difference + 1..],
+                )
+        }
+        1 => equal(
+            &longer[first_difference + 1..],
+            &shorter[first_difference..],
+        ),
+        _ => false,
+    }
+}
+
+/// The Levenshtein distance between two tags, counting whole subtags and ignoring case, so
+/// `en-US` and `en-GB` are `1` apart and `en` and `en-Latn-US` are `2` apart.
+fn subtag_distance(a: &str, b: &str) -> usize {
+    let b_subtags: Vec<&str> = b.split('-').collect();
+    let mut previous: Vec<usize> = (0..=b_subtags.len()).collect();
+    for (i, a_subtag) in a.split('-').enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_subtag) in b_subtags.iter().enumerate() {
+            let replace = previous[j] + usize::from(!a_subtag.eq_ignore_ascii_case(b_subtag));
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b_subtags.len()]
+}
+
+/// Find the quality a raw Accept-Language header value gives to `tag`, ignoring case, without
+/// allocating. `None` is returned when the tag isn't listed or has a quality of `0`. When it's
+/// listed more than once, the highest quality is used. Spaces inside a tag are removed like in
+/// [`parse`](parse), which is the only case that copies it.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::accepts;
+///
+/// assert_eq!(accepts("en-US, de;q=0.5", "DE"), Some(0.5));
+/// assert_eq!(accepts("en-US, de;q=0", "de"), None);
+/// assert_eq!(accepts("en-US", "de"), None);
+/// ```
+pub fn accepts(raw_languages: &str, tag: &str) -> Option<f32> {
+    scan_stripped_languages(raw_languages)
+        .filter(|(name, _)| name.eq_ignore_ascii_case(tag))
+        .map(|(_, quality)| quality)
+        .max()
+        .filter(|&quality| quality > Quality::MIN)
+        .map(Quality::as_f32)
+}
+
+/// Check whether the user accepts the server's default language with at least `min_quality`,
+/// so it can be served without negotiating. The default matches a user language that equals it,
+/// ignoring case, or that falls back to it like in [`select_lossy`](select_lossy), so a default
+/// of `en` is acceptable to a user asking for `en-US`. Nothing is allocated.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::default_is_acceptable;
+///
+/// assert!(default_is_acceptable("en-US, de;q=0.5", "en", 0.8));
+/// assert!(!default_is_acceptable("en-US, de;q=0.5", "de", 0.8));
+/// ```
+pub fn default_is_acceptable(raw_languages: &str, server_default: &str, min_quality: f32) -> bool {
+    let min_quality = Quality::clamped(min_quality);
+    scan_languages(raw_languages).any(|(name, quality)| {
+        quality > Quality::MIN
+            && quality >= min_quality
+            && (name.eq_ignore_ascii_case(server_default)
+                || find_supported_fallback(name, &[server_default]).is_some())
+    })
+}
+
+/// The quality of the user's preferred language, the one [`parse`](parse) puts first, as a
+/// confidence score for logs. `None` is returned for a header without languages, or when every
+/// language has a quality of `0`. Nothing is allocated.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::top_quality;
+///
+/// assert_eq!(top_quality("de;q=0.5, en-US;q=0.8"), Some(0.8));
+/// assert_eq!(top_quality(""), None);
+/// ```
+pub fn top_quality(raw_languages: &str) -> Option<f32> {
+    scan_languages(raw_languages)
+        .map(|(_, quality)| quality)
+        .max()
+        .filter(|&quality| quality > Quality::MIN)
+        .map(Quality::as_f32)
+}
+
+/// Check whether an Accept-Language header value accepts any language through the `*` range.
+/// A wildcard with a quality of `0` (e.g. `*;q=0`) rejects everything that wasn't explicitly
+/// listed, so it doesn't count.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::accepts_any;
+///
+/// assert!(accepts_any("en-US, *;q=0.5"));
+/// assert!(!accepts_any("en-US, *;q=0"));
+/// ```
+pub fn accepts_any(raw_languages: &str) -> bool {
+    parse_with_quality(raw_languages)
+        .iter()
+        .any(|l| l.0 == "*" && l.1 > 0.0)
+}
+
+/// Check whether the user accepts any of the `candidates`, compared case-insensitively. Unlike
+/// [`intersection`](intersection), this stops at the first match and doesn't allocate or sort,
+/// which suits quick checks like feature gating. Languages with a quality of `0` don't count.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::accepts_any_of;
+///
+/// assert!(accepts_any_of("en-US, de;q=0.5", &["de", "fr"]));
+/// assert!(!accepts_any_of("en-US, de;q=0", &["de", "fr"]));
+/// ```
+pub fn accepts_any_of(raw_languages: &str, candidates: &[&str]) -> bool {
+    scan_languages(raw_languages).any(|(name, quality)| {
+        quality > Quality::MIN && candidates.iter().any(|c| c.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Format a quality the way the HTTP grammar expects it after `q=`: rounded to at most three
+/// decimals without trailing zeros, and never in scientific notation. Values outside of `0..=1`
+/// are clamped, so `1.0` formats as `1` and `0.0` as `0`.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::format_quality;
+///
+/// assert_eq!(format_quality(1.0), "1");
+/// assert_eq!(format_quality(0.75), "0.75");
+/// assert_eq!(format_quality(0.3333), "0.333");
+/// ```
+pub fn format_quality(quality: f32) -> String {
+    match quality_to_millis(quality) {
+        0 => String::from("0"),
+        1000 => String::from("1"),
+        millis => format!("0.{:03}", millis).trim_end_matches('0').to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accepts, accepts_any, accepts_any_of, basic_filter, bundle_candidates, canonical_case,
+        closest, default_is_acceptable, equivalent, fallback_chain, filter_languages,
+        format_quality, header_diff, intersection, intersection_all_lossy, intersection_and_unused,
+        intersection_count, intersection_defaults_only, intersection_lossy,
+        intersection_lossy_flagged, intersection_lossy_wildcard, intersection_min_quality,
+        intersection_opt, intersection_ordered, intersection_ordered_with_quality,
+        intersection_tiers, intersection_with_missing, intersection_with_quality,
+        intersection_with_quality_wildcard, intersection_with_wildcard, is_canonical_case,
+        is_region_specific, is_typo_of, is_valid_ordered, lookup, most_specific_match,
+        negotiate_explained, negotiate_layered, negotiate_priorities, negotiate_status,
+        negotiate_with_fallbacks, negotiate_with_prior, negotiation_key, parse, parse_batch,
+        parse_bounded, parse_bytes, parse_cow, parse_fixed, parse_indexed, parse_language_tags,
+        parse_lenient, parse_opt, parse_percentages, parse_posix, parse_primary,
+        parse_rescaled_top, parse_specific_first, parse_specificity_within_language, parse_strict,
+        parse_to_btree, parse_to_map, parse_unsorted, parse_with_default_flag, parse_with_original,
+        parse_with_quality, parse_with_quality_typed, parse_with_rejections, retain_languages,
+        search_path, select, select_detailed, select_lossy, sort_languages, sort_supported,
+        split_languages, subtag_distance, switcher_order, tag_refs, tags_only, top_n, top_quality,
+        two_letter_code, AcceptLanguage, AcceptLanguageBuilder, Error, Language, LanguageTag,
+        Matcher, Negotiation, NegotiationReason, Negotiator, Quality, Wildcard, TWO_LETTER_CODES,
+    };
+    use std::borrow::Cow;
+    use std::cmp::Reverse;
+    use std::collections::BTreeSet;
+    use std::convert::TryFrom;
+    use std::time::Instant;
+
+    static MOCK_ACCEPT_LANGUAGE: &str = "en-US, de;q=0.7, zh-Hant, jp;q=0.1";
+    static AVIALABLE_LANGUAGES: &[&str] =
+        &["da", "de", "en-US", "it", "jp", "zh", "zh-Hans", "zh-Hant"];
+
+    /// Headers sent by browsers, next to the `navigator.languages` they report for the same
+    /// settings.
+    static NAVIGATOR_LANGUAGES: &[(&str, &[&str])] = &[
+        // Chrome
+        ("en-US,en;q=0.9", &["en-US", "en"]),
+        (
+            "zh-CN,zh;q=0.9,en;q=0.8,en-GB;q=0.7,en-US;q=0.6",
+            &["zh-CN", "zh", "en", "en-GB", "en-US"],
+        ),
+        (
+            "de-DE,de;q=0.9,en-US;q=0.8,en;q=0.7",
+            &["de-DE", "de", "en-US", "en"],
+        ),
+        // Firefox
+        ("en-US,en;q=0.5", &["en-US", "en"]),
+        (
+            "fr,fr-FR;q=0.8,en-US;q=0.5,en;q=0.3",
+            &["fr", "fr-FR", "en-US", "en"],
+        ),
+        ("de,en-US;q=0.7,en;q=0.3", &["de", "en-US", "en"]),
+        // Safari
+        ("en-GB,en;q=0.9", &["en-GB", "en"]),
+        ("ja-JP,ja;q=0.9", &["ja-JP", "ja"]),
+        // Edge
+        (
+            "en-US,en;q=0.9,es-419;q=0.8,es;q=0.7",
+            &["en-US", "en", "es-419", "es"],
+        ),
+    ];
+
+    fn matches_navigator(raw_languages: &str, navigator: &[&str]) -> bool {
+        parse(raw_languages) == navigator
+    }
+
+    #[test]
+    fn it_creates_a_new_language_from_a_string() {
+        let language = Language::new("en-US;q=0.7");
+        assert_eq!(
+            language,
+            Language {
+                name: String::from("en-US"),
+                quality: Quality(700),
+            }
+        )
+    }
+
+    #[test]
+    fn it_creates_a_new_language_from_a_string_with_lowercase_country() {
+        let language = Language::new("en-us;q=0.7");
+        assert_eq!(
+            language,
+            Language {
+                name: String::from("en-US"),
+                quality: Quality(700),
+            }
+        )
+    }
+
+    #[test]
+    fn it_creates_a_new_language_from_a_string_with_a_default_quality() {
+        let language = Language::new("en-US");
+        assert_eq!(
+            language,
+            Language {
+                name: String::from("en-US"),
+                quality: Quality::MAX,
+            }
+        )
+    }
+
+    #[test]
+    fn it_parses_quality() {
+        let quality = Language::quality_with_default("q=0.5");
+        assert_eq!(quality, 0.5)
+    }
+
+    #[test]
+    fn it_parses_an_invalid_quality() {
+        let quality = Language::quality_with_default("q=yolo");
+        assert_eq!(quality, 0.0)
+    }
+
+    #[test]
+    fn it_parses_a_valid_accept_language_header() {
+        let user_languages = parse(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(
+            user_languages,
+            vec![
+                String::from("en-US"),
+                String::from("zh-Hant"),
+                String::from("de"),
+                String::from("jp"),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_parses_an_empty_accept_language_header() {
+        let user_languages = parse("");
+        assert_eq!(user_languages.len(), 0)
+    }
+
+    #[test]
+    fn it_parses_an_invalid_accept_language_header() {
+        let user_languages_one = parse("q");
+        let user_languages_two = parse(";q");
+        let user_languages_three = parse("q-");
+        let user_languages_four = parse("en;q=");
+        assert_eq!(user_languages_one, vec![String::from("q")]);
+        assert_eq!(user_languages_two.len(), 0);
+        assert_eq!(user_languages_three, vec![String::from("q-")]);
+        assert_eq!(user_languages_four, vec![String::from("en")]);
+        for raw in &[";", ";q=0.5", " ;q", ",", " , ;q=1"] {
+            assert!(parse(raw).is_empty(), "{:?}", raw);
+        }
+        for raw in &["en", "en;", "en;q", "en;q=", "en;q= ", "en ; q =  "] {
+            assert_eq!(
+                parse_with_quality(raw),
+                vec![(String::from("en"), 1.0)],
+                "{:?}",
+                raw
+            );
+        }
+        assert_eq!(
+            parse_with_quality("en;q=abc, de;q=0.5"),
+            vec![(String::from("de"), 0.5), (String::from("en"), 0.0)]
+        );
+    }
+
+    #[test]
+    fn it_sorts_languages_by_quality() {
+        let user_languages = parse("en-US, de;q=0.1, jp;q=0.7");
+        assert_eq!(
+            user_languages,
+            vec![
+                String::from("en-US"),
+                String::from("jp"),
+                String::from("de"),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_returns_language_intersection() {
+        let common_languages = intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES);
+        assert_eq!(
+            common_languages,
+            vec![
+                String::from("en-US"),
+                String::from("zh-Hant"),
+                String::from("de"),
+                String::from("jp")
+            ]
+        )
+    }
+
+    #[test]
+    fn it_returns_language_intersection_ordered() {
+        let common_languages = intersection_ordered(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES);
+        assert_eq!(
+            common_languages,
+            vec![
+                String::from("en-US"),
+                String::from("zh-Hant"),
+                String::from("de"),
+                String::from("jp")
+            ]
+        )
+    }
+
+    #[test]
+    fn it_returns_language_intersection_with_quality() {
+        let common_languages = intersection_with_quality(MOCK_ACCEPT_LANGUAGE, &["en-US", "jp"]);
+        assert_eq!(
+            common_languages,
+            vec![(String::from("en-US"), 1.0), (String::from("jp"), 0.1)]
+        )
+    }
+
+    #[test]
+    fn it_returns_language_intersection_ordered_with_quality() {
+        let common_languages =
+            intersection_ordered_with_quality(MOCK_ACCEPT_LANGUAGE, &["en-US", "jp"]);
+        assert_eq!(
+            common_languages,
+            vec![(String::from("en-US"), 1.0), (String::from("jp"), 0.1)]
+        )
+    }
+
+    #[test]
+    fn it_returns_an_empty_array_when_no_intersection() {
+        let common_languages = intersection(MOCK_ACCEPT_LANGUAGE, &["fr", "en-GB"]);
+        assert_eq!(common_languages.len(), 0)
+    }
+
+    #[test]
+    fn it_parses_traditional_chinese() {
+        assert_eq!(parse("zh-Hant"), &["zh-Hant"]);
+    }
+
+    #[test]
+    fn it_implements_case_insensitive_equality() {
+        assert_eq!(Language::new("en-US"), Language::new("en-us"));
+        assert_eq!(Language::new("en-US;q=0.7"), Language::new("en-us;q=0.7"));
+        assert_ne!(Language::new("en"), Language::new("en-US"));
+        assert_ne!(Language::new("en;q=0.7"), Language::new("en;q=0.8"));
+        assert_ne!(Language::new("en;q=0.7"), Language::new("en-US;q=0.7"));
+    }
+
+    #[test]
+    fn it_accepts_any_language_with_a_wildcard() {
+        assert!(accepts_any("en, *"));
+        assert!(!accepts_any("*;q=0"));
+        assert!(!accepts_any("en, de"));
+    }
+
+    #[test]
+    fn it_orders_languages_consistently_with_equality() {
+        let mut languages = BTreeSet::new();
+        languages.insert(Language::new("en;q=0.5"));
+        languages.insert(Language::new("EN;q=0.5"));
+        languages.insert(Language::new("de;q=0.5"));
+        languages.insert(Language::new("en;q=0.9"));
+        let names: Vec<&str> = languages.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, vec!["en", "de", "en"]);
+        assert_eq!(languages.len(), 3);
+    }
+
+    #[test]
+    fn it_keeps_header_order_for_equal_qualities() {
+        assert_eq!(
+            parse("zh, en, de;q=0.5, da;q=0.5"),
+            vec!["zh", "en", "de", "da"]
+        );
+    }
+
+    #[test]
+    fn it_parses_to_a_map_keeping_the_highest_quality() {
+        let user_languages = parse_to_map("de;q=0.7, de;q=0.3, EN");
+        assert_eq!(user_languages.len(), 2);
+        assert_eq!(user_languages["de"], 0.7);
+        assert_eq!(user_languages["en"], 1.0);
+    }
+
+    #[test]
+    fn it_parses_to_a_btree_ordered_by_descending_quality() {
+        let user_languages = parse_to_btree("de;q=0.5, en-US, jp;q=0.1, en-GB, fr;q=0.5");
+        let groups: Vec<(Reverse<Quality>, Vec<String>)> = user_languages.into_iter().collect();
+        assert_eq!(
+            groups,
+            vec![
+                (
+                    Reverse(Quality::MAX),
+                    vec![String::from("en-US"), String::from("en-GB")]
+                ),
+                (
+                    Reverse(Quality(500)),
+                    vec![String::from("de"), String::from("fr")]
+                ),
+                (Reverse(Quality(100)), vec![String::from("jp")]),
+            ]
+        )
+    }
+
+    #[test]
+    fn it_parses_headers_without_language_names() {
+        assert_eq!(parse("").len(), 0);
+        assert_eq!(parse(",").len(), 0);
+        assert_eq!(parse(",,, ,").len(), 0);
+        assert_eq!(parse(";q=1").len(), 0);
+        assert_eq!(parse(";q=0.5").len(), 0);
+        assert_eq!(parse("  ").len(), 0);
+        assert_eq!(parse("\t, \t;q=0.5").len(), 0);
+    }
+
+    #[test]
+    fn it_parses_an_optional_header() {
+        assert_eq!(parse_opt(None).len(), 0);
+        assert_eq!(parse_opt(Some("")).len(), 0);
+        assert_eq!(parse_opt(Some("en-US")), vec![String::from("en-US")]);
+    }
+
+    #[test]
+    fn it_returns_language_intersection_for_an_optional_header() {
+        assert_eq!(intersection_opt(None, AVIALABLE_LANGUAGES).len(), 0);
+        assert_eq!(intersection_opt(Some(""), AVIALABLE_LANGUAGES).len(), 0);
+        assert_eq!(
+            intersection_opt(Some("en-US"), AVIALABLE_LANGUAGES),
+            vec![String::from("en-US")]
+        );
+    }
+
+    #[test]
+    fn it_parses_more_specific_tags_first() {
+        assert_eq!(parse_specific_first("en, en-US"), vec!["en-US", "en"]);
+        assert_eq!(
+            parse_specific_first("en, en-US, en-US-x-foo, de;q=0.5, de-CH;q=0.5, en-GB;q=0.1"),
+            vec!["en-US-x-foo", "en-US", "en", "de-CH", "de", "en-GB"]
+        );
+        assert_eq!(parse_specific_first("en, enx"), vec!["en", "enx"]);
+        assert_eq!(parse("en, en-US"), vec!["en", "en-US"]);
+    }
+
+    #[test]
+    fn it_reports_conflicting_qualities_in_strict_mode() {
+        assert_eq!(
+            parse_strict("en;q=0.5, de, EN;q=0.9"),
+            Err(Error::ConflictingQuality {
+                tag: String::from("en"),
+                qualities: vec![0.5, 0.9],
+            })
+        );
+        assert_eq!(
+            parse_strict("en;q=0.5, de, en;q=0.5"),
+            Ok(vec![
+                String::from("de"),
+                String::from("en"),
+                String::from("en")
+            ])
+        );
+        assert_eq!(parse_to_map("en;q=0.5, en;q=0.9")["en"], 0.9);
+    }
+
+    #[test]
+    fn it_displays_parse_errors() {
+        let error = Error::ConflictingQuality {
+            tag: String::from("en"),
+            qualities: vec![0.5, 0.9],
+        };
+        assert_eq!(
+            error.to_string(),
+            "the language `en` has conflicting qualities: 0.5, 0.9"
+        );
+    }
+
+    #[test]
+    fn it_displays_quality_errors() {
+        let error = Error::QualityOutOfRange { quality: 1.5 };
+        assert_eq!(Quality::try_from(1.5), Err(error.clone()));
+        assert_eq!(
+            error.to_string(),
+            "the quality 1.5 is outside of the range 0 to 1"
+        );
+        assert_eq!(
+            Quality::from_millis(1001),
+            Err(Error::QualityOutOfRange { quality: 1.001 })
+        );
+        assert_eq!(
+            AcceptLanguageBuilder::new()
+                .add_with_quality("en", -0.5)
+                .unwrap_err(),
+            Error::QualityOutOfRange { quality: -0.5 }
+        );
+        let error: Box<dyn std::error::Error> = Box::new(error);
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn it_accepts_any_of_the_candidates() {
+        assert!(accepts_any_of(MOCK_ACCEPT_LANGUAGE, &["fr", "DE"]));
+        assert!(!accepts_any_of(MOCK_ACCEPT_LANGUAGE, &["fr", "it"]));
+        assert!(!accepts_any_of("en-US, fr;q=0", &["fr"]));
+        assert!(accepts_any_of("en-US, fr ; q = 0.5", &["fr"]));
+    }
+
+    #[test]
+    fn it_parses_rejected_languages_separately() {
+        assert_eq!(
+            parse_with_rejections("en, de;q=0"),
+            (vec![(String::from("en"), 1.0)], vec![String::from("de")])
+        );
+        assert_eq!(parse_with_rejections(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn it_formats_qualities() {
+        assert_eq!(format_quality(1.0), "1");
+        assert_eq!(format_quality(0.0), "0");
+        assert_eq!(format_quality(0.5), "0.5");
+        assert_eq!(format_quality(0.7), "0.7");
+        assert_eq!(format_quality(0.75), "0.75");
+        assert_eq!(format_quality(0.333), "0.333");
+        assert_eq!(format_quality(0.001), "0.001");
+        assert_eq!(format_quality(0.0004), "0");
+        assert_eq!(format_quality(0.9996), "1");
+        assert_eq!(format_quality(0.12345), "0.123");
+        assert_eq!(format_quality(1e-10), "0");
+        assert_eq!(format_quality(7.0), "1");
+        assert_eq!(format_quality(-1.0), "0");
+        assert_eq!(format_quality(f32::NAN), "0");
+    }
+
+    #[test]
+    fn it_selects_a_language() {
+        assert_eq!(select(MOCK_ACCEPT_LANGUAGE, &["jp", "DE"], "fr"), "DE");
+        assert_eq!(select("en-US, de;q=0", &["de"], "fr"), "fr");
+        assert_eq!(select("", &["de"], "fr"), "fr");
+    }
+
+    #[test]
+    fn it_selects_a_language_with_a_base_language_fallback() {
+        assert_eq!(
+            select_lossy(MOCK_ACCEPT_LANGUAGE, &["en-US", "de"], "fr"),
+            "en-US"
+        );
+        assert_eq!(
+            select_lossy("en-US, en-GB;q=0.5", &["en", "de"], "fr"),
+            "en"
+        );
+        assert_eq!(select_lossy("en-US, de;q=0.5", &["fr", "es"], "fr"), "fr");
+    }
+
+    #[test]
+    fn it_builds_fallback_chains() {
+        assert_eq!(fallback_chain("en"), vec!["en"]);
+        assert_eq!(fallback_chain("en-US"), vec!["en-US", "en"]);
+        assert_eq!(
+            fallback_chain("de-DE-u-co-phonebk"),
+            vec!["de-DE-u-co-phonebk", "de-DE-u-co", "de-DE", "de"]
+        );
+        assert_eq!(fallback_chain("en-x-a"), vec!["en-x-a", "en"]);
+        assert_eq!(fallback_chain("").len(), 0);
+    }
+
+    #[test]
+    fn it_builds_a_search_path() {
+        assert_eq!(search_path("fr-CA, en;q=0.8"), vec!["fr-CA", "fr", "en"]);
+        assert_eq!(
+            search_path("en-US, en-GB;q=0.9, de;q=0"),
+            vec!["en-US", "en", "en-GB"]
+        );
+        assert_eq!(search_path("fr-CA, *;q=0.5"), vec!["fr-CA", "fr"]);
+        assert_eq!(search_path("*, de;q=0"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_checks_the_range_of_qualities() {
+        assert_eq!(Quality::try_from(0.5), Ok(Quality(500)));
+        assert_eq!(Quality::try_from(1.0), Ok(Quality::MAX));
+        assert_eq!(Quality::try_from(0.0), Ok(Quality::MIN));
+        assert!(Quality::try_from(1.01).is_err());
+        assert!(Quality::try_from(-0.1).is_err());
+        assert!(Quality::try_from(f32::NAN).is_err());
+        assert_eq!(Quality::from_millis(1000), Ok(Quality::MAX));
+        assert!(Quality::from_millis(1001).is_err());
+        assert_eq!(Quality(250).as_f32(), 0.25);
+        assert_eq!(Quality(250).to_string(), "0.25");
+    }
+
+    #[test]
+    fn it_orders_qualities() {
+        let mut qualities = vec![Quality(500), Quality::MAX, Quality::MIN, Quality(501)];
+        qualities.sort();
+        assert_eq!(
+            qualities,
+            vec![Quality::MIN, Quality(500), Quality(501), Quality::MAX]
+        );
+    }
+
+    #[test]
+    fn it_parses_typed_qualities() {
+        assert_eq!(
+            parse_with_quality_typed("de;q=0.5, en-US, fr;q=2, it;q=-1"),
+            vec![
+                (String::from("en-US"), Quality::MAX),
+                (String::from("fr"), Quality::MAX),
+                (String::from("de"), Quality(500)),
+                (String::from("it"), Quality::MIN),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_parses_a_folded_header() {
+        assert_eq!(parse("en-US,\r\n de;q=0.7"), vec!["en-US", "de"]);
+        assert_eq!(
+            parse_with_quality("en-US,\r\n\tde;q=\r\n 0.7"),
+            vec![(String::from("en-US"), 1.0), (String::from("de"), 0.7)]
+        );
+    }
+
+    #[test]
+    fn it_finds_the_most_specific_match() {
+        let supported = &["en", "en-US", "de"];
+        assert_eq!(
+            most_specific_match("en", supported),
+            Some(String::from("en-US"))
+        );
+        assert_eq!(
+            most_specific_match("en-US, de;q=0.5", supported),
+            Some(String::from("en-US"))
+        );
+        assert_eq!(most_specific_match("en-GB, de;q=0.5", supported), None);
+        assert_eq!(
+            most_specific_match("en", &["en-Latn-US", "en-US", "en"]),
+            Some(String::from("en-Latn-US"))
+        );
+        assert_eq!(most_specific_match("", supported), None);
+    }
+
+    #[test]
+    fn it_parses_with_the_original_tag() {
+        assert_eq!(
+            parse_with_original(" en-us ;q=0.5, DE-latn-ch-1996, x-Whatever"),
+            vec![
+                (
+                    String::from("de-Latn-CH-1996"),
+                    String::from("DE-latn-ch-1996")
+                ),
+                (String::from("x-whatever"), String::from("x-Whatever")),
+                (String::from("en-US"), String::from("en-us")),
+            ]
+        );
+        assert_eq!(
+            parse_with_original("sgn-BE-FR, az-Arab-x-AZE-derbend"),
+            vec![
+                (String::from("sgn-BE-FR"), String::from("sgn-BE-FR")),
+                (
+                    String::from("az-Arab-x-aze-derbend"),
+                    String::from("az-Arab-x-AZE-derbend")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_creates_languages_from_pathological_segments() {
+        assert_eq!(
+            Language::new(";;;"),
+            Language {
+                name: String::new(),
+                quality: Quality::MAX,
+            }
+        );
+        assert_eq!(
+            Language::new("="),
+            Language {
+                name: String::from("="),
+                quality: Quality::MAX,
+            }
+        );
+        assert_eq!(
+            Language::new("q="),
+            Language {
+                name: String::from("q="),
+                quality: Quality::MAX,
+            }
+        );
+        assert_eq!(
+            Language::new("en;="),
+            Language {
+                name: String::from("en"),
+                quality: Quality::MAX,
+            }
+        );
+        assert_eq!(Language::quality_with_default("q="), 1.0);
+        assert_eq!(Language::quality_with_default("="), 1.0);
+        assert_eq!(Language::quality_with_default(""), 1.0);
+        assert_eq!(parse(";;;, =, q="), vec!["=", "q="]);
+    }
+
+    #[test]
+    fn it_parses_a_batch_of_headers() {
+        let raw_headers = &[
+            MOCK_ACCEPT_LANGUAGE,
+            "",
+            "de;q=0.5, fr",
+            ",,",
+            "en-US,\r\n de;q=0.7",
+        ];
+        let expected: Vec<Vec<String>> = raw_headers.iter().map(|raw| parse(raw)).collect();
+        assert_eq!(parse_batch(raw_headers), expected);
+        assert_eq!(parse_batch(&[]).len(), 0);
+    }
+
+    #[test]
+    fn it_returns_language_intersection_with_missing_languages() {
+        let (common_languages, missing_languages) =
+            intersection_with_missing("fr, en-US;q=0.8, it;q=0.5, es;q=0", &["en-US", "de"]);
+        assert_eq!(common_languages, vec![String::from("en-US")]);
+        assert_eq!(
+            missing_languages,
+            vec![String::from("fr"), String::from("it")]
+        );
+        assert_eq!(
+            intersection_with_missing(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES).0,
+            intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES)
+        );
+    }
+
+    #[test]
+    fn it_leaves_rejected_and_repeated_languages_out_of_the_missing_languages() {
+        assert_eq!(
+            intersection_with_missing("en;q=0, tlh;q=0", &["en"]),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            intersection_with_missing("en, en;q=0.5, tlh, tlh", &["en"]),
+            (vec![String::from("en")], vec![String::from("tlh")])
+        );
+    }
+
+    #[test]
+    fn it_matches_supported_wildcards() {
+        let supported = &["en-*", "de"];
+        assert_eq!(
+            intersection("en-US, de, en, en-GB;q=0.5", supported),
+            vec!["en-US", "de", "en-GB"]
+        );
+        assert_eq!(
+            intersection_with_quality("en-US, de;q=0.8", supported),
+            vec![(String::from("en-US"), 1.0), (String::from("de"), 0.8)]
+        );
+        assert_eq!(
+            intersection_with_missing("en-US, enx-US, de", supported),
+            (
+                vec![String::from("en-US"), String::from("de")],
+                vec![String::from("enx-US")]
+            )
+        );
+    }
+
+    #[test]
+    fn it_builds_a_header() {
+        let builder = AcceptLanguageBuilder::new()
+            .add_with_quality("jp", 0.1)
+            .unwrap()
+            .add("en-US")
+            .add_with_quality("de", 0.7)
+            .unwrap()
+            .add_with_quality("zh-Hant", 1.0)
+            .unwrap();
+        assert_eq!(builder.build(), "jp;q=0.1, en-US, de;q=0.7, zh-Hant");
+        let header = builder.sort_by_quality(true).build();
+        assert_eq!(header, "en-US, zh-Hant, de;q=0.7, jp;q=0.1");
+        assert_eq!(parse(&header), parse(MOCK_ACCEPT_LANGUAGE));
+        assert_eq!(AcceptLanguageBuilder::new().build(), "");
+    }
+
+    #[test]
+    fn it_formats_built_qualities() {
+        let header = AcceptLanguageBuilder::new()
+            .add_with_quality("en", 0.12345)
+            .unwrap()
+            .add_with_quality("de", 0.5)
+            .unwrap()
+            .add_with_quality("fr", 0.0)
+            .unwrap()
+            .build();
+        assert_eq!(header, "en;q=0.123, de;q=0.5, fr;q=0");
+        assert!(AcceptLanguageBuilder::new()
+            .add_with_quality("en", 1.5)
+            .is_err());
+        assert!(AcceptLanguageBuilder::new()
+            .add_with_quality("en", f32::NAN)
+            .is_err());
+    }
+
+    /// A small xorshift generator, so the property tests are reproducible without extra
+    /// dependencies.
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn random_header(state: &mut u64) -> String {
+        const TAGS: &[&str] = &[
+            "en",
+            "EN",
+            "en-US",
+            "de",
+            "*",
+            "zh-Hant",
+            "",
+            " ",
+            "x-klingon",
+            "ü",
+            "en-",
+            "-",
+        ];
+        const QUALITIES: &[&str] = &[
+            "",
+            ";q=1",
+            ";q=0",
+            ";q=0.5",
+            ";q=0.500",
+            ";q=-1",
+            ";q=2",
+            ";q=NaN",
+            ";q=inf",
+            ";q=",
+            ";q",
+            ";",
+            ";;q=0.1",
+            ";level=1",
+            ";q=0.1;q=0.9",
+            ";q=0.1234",
+            ";q=.5",
+            ";q=\u{1F600}",
+        ];
+        const SEPARATORS: &[&str] = &[",", ", ", ",,", " ,\t", "\r\n ,"];
+        let mut header = String::new();
+        for _ in 0..next_random(state) % 6 {
+            header.push_str(TAGS[next_random(state) as usize % TAGS.len()]);
+            header.push_str(QUALITIES[next_random(state) as usize % QUALITIES.len()]);
+            header.push_str(SEPARATORS[next_random(state) as usize % SEPARATORS.len()]);
+        }
+        header
+    }
+
+    #[test]
+    fn it_never_panics_in_strict_mode() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..10_000 {
+            let header = random_header(&mut state);
+            match parse_strict(&header) {
+                Ok(languages) => assert_eq!(languages, parse(&header), "{:?}", header),
+                Err(Error::ConflictingQuality { tag, qualities }) => {
+                    assert!(qualities.len() > 1, "{:?}", header);
+                    assert!(
+                        parse(&header).iter().any(|l| l.eq_ignore_ascii_case(&tag)),
+                        "{:?}",
+                        header
+                    );
+                }
+                Err(Error::InvalidQuality { tag, quality }) => {
+                    assert!(header.contains(&quality), "{:?}", header);
+                    assert!(parse(&header).contains(&tag), "{:?}", header);
+                }
+                Err(Error::QualityOutOfRange { quality }) => {
+                    assert!(!(0.0..=1.0).contains(&quality), "{:?}", header);
+                }
+                Err(Error::NotALanguageTag { tag }) => {
+                    assert!(parse(&header).contains(&tag), "{:?}", header);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_returns_a_lossy_intersection() {
+        assert_eq!(
+            intersection_lossy("en-US, en-GB;q=0.8, fr;q=0, fr-CA;q=0.5", &["fr", "en"]),
+            vec!["en"]
+        );
+        assert_eq!(
+            intersection_lossy(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES)
+        );
+        assert!(intersection_lossy("", &["en"]).is_empty());
+    }
+
+    #[test]
+    fn it_returns_bundle_candidates() {
+        assert_eq!(
+            bundle_candidates(
+                "en-US, de-AT;q=0.7, fr;q=0.2",
+                &["de", "en-US", "it"],
+                "messages",
+                "ftl"
+            ),
+            vec!["messages.en-US.ftl", "messages.de.ftl"]
+        );
+    }
+
+    #[test]
+    fn it_matches_scripts_implied_by_chinese_regions() {
+        let supported = &["en", "zh-Hans", "zh-Hant"];
+        assert_eq!(select_lossy("zh-TW", supported, "en"), "zh-Hant");
+        assert_eq!(select_lossy("zh-hk, fr;q=0.5", supported, "en"), "zh-Hant");
+        assert_eq!(select_lossy("zh-CN", supported, "en"), "zh-Hans");
+        assert_eq!(select_lossy("zh-TW", &["zh", "zh-Hans"], "en"), "en");
+        assert_eq!(
+            intersection_lossy("zh-TW, zh-SG;q=0.5, zh-Hant;q=0.1", supported),
+            vec!["zh-Hant", "zh-Hans"]
+        );
+        assert!(intersection_lossy("zh-Hant-TW", &["zh-Hant"]).is_empty());
+    }
+
+    #[test]
+    fn it_returns_every_lossy_match() {
+        assert_eq!(
+            intersection_all_lossy("en-US", &["en", "en-US"]),
+            vec!["en-US", "en"]
+        );
+        assert_eq!(
+            intersection_all_lossy("zh-TW, en-GB;q=0.5, en;q=0.4", &["en", "zh", "zh-Hant"]),
+            vec!["zh-Hant", "en"]
+        );
+        assert_eq!(
+            intersection_all_lossy("de-CH, de;q=0", &["de", "de-CH"]),
+            vec!["de-CH"]
+        );
+        assert_eq!(intersection_lossy("en-US", &["en", "en-US"]), vec!["en-US"]);
+    }
+
+    #[test]
+    fn it_only_matches_private_use_tags_exactly() {
+        let supported = &["en", "x-pig-latin"];
+        assert_eq!(parse("X-Pig-Latin, en"), vec!["X-Pig-Latin", "en"]);
+        assert_eq!(intersection("x-pig-latin", supported), vec!["x-pig-latin"]);
+        assert_eq!(select_lossy("x-pig-latin", supported, "de"), "x-pig-latin");
+        assert_eq!(select_lossy("x-pig-latin", &["en", "x"], "de"), "de");
+        assert!(intersection_lossy("x-pig-latin, x-klingon", &["en", "x"]).is_empty());
+        assert_eq!(
+            intersection_all_lossy("x-pig-latin-ext", &["en", "x", "x-pig-latin"]),
+            Vec::<String>::new()
+        );
+        assert_eq!(fallback_chain("x-pig-latin"), vec!["x-pig-latin"]);
+        assert_eq!(
+            search_path("x-pig-latin, en-US"),
+            vec!["x-pig-latin", "en-US", "en"]
+        );
+        assert_eq!(
+            most_specific_match("x-pig", &["x-pig-latin", "x-pig"]),
+            Some(String::from("x-pig"))
+        );
+        assert_eq!(most_specific_match("x-pig", &["en", "x-pig-latin"]), None);
+    }
+
+    #[test]
+    fn it_parses_languages_into_percentages() {
+        assert_eq!(
+            parse_percentages("en, de;q=0.5"),
+            vec![(String::from("en"), 67), (String::from("de"), 33)]
+        );
+        assert_eq!(
+            parse_percentages("en, de, fr, it;q=0"),
+            vec![
+                (String::from("en"), 34),
+                (String::from("de"), 33),
+                (String::from("fr"), 33)
+            ]
+        );
+        let percentages = parse_percentages(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(
+            percentages.iter().map(|(_, p)| u32::from(*p)).sum::<u32>(),
+            100
+        );
+        assert!(parse_percentages("").is_empty());
+        assert!(parse_percentages("en;q=0").is_empty());
+    }
+
+    #[test]
+    fn it_parses_bytes() {
+        assert_eq!(
+            parse_bytes(MOCK_ACCEPT_LANGUAGE.as_bytes()),
+            parse(MOCK_ACCEPT_LANGUAGE)
+        );
+        assert_eq!(parse_bytes("fr-CH, ü".as_bytes()), vec!["fr-CH", "ü"]);
+        assert!(parse_bytes(&[b'e', b'n', 0xc3, 0x28]).is_empty());
+        assert!(parse_bytes(b"").is_empty());
+    }
+
+    #[test]
+    fn it_removes_duplicates_when_parsing_with_quality() {
+        assert_eq!(
+            parse_with_quality("en-US;q=0.4, de, en-US;q=0.9, EN-us"),
+            vec![(String::from("de"), 1.0), (String::from("EN-us"), 1.0)]
+        );
+        assert_eq!(
+            parse_with_quality("en-US;q=0.9, en-US;q=0.4, de"),
+            vec![(String::from("de"), 1.0), (String::from("en-US"), 0.9)]
+        );
+        assert_eq!(
+            parse_with_quality_typed("en-US;q=0.9, en-US;q=0.4, de"),
+            vec![
+                (String::from("de"), Quality::MAX),
+                (String::from("en-US"), Quality(900))
+            ]
+        );
+    }
+
+    #[test]
+    fn it_compares_equivalent_headers() {
+        assert!(equivalent("en, de;q=0.5", "en , de ;q=0.5"));
+        assert!(equivalent("en, de;q=0.5", "EN;q=0.9, de;q=0.1, en;q=0.2"));
+        assert!(equivalent("fr;q=0, en", "en;q=1.0, fr;q=0"));
+        assert!(equivalent("", " , "));
+        assert!(!equivalent("en, de;q=0.5", "de, en"));
+        assert!(!equivalent("en, de;q=0.5", "en, de"));
+        assert!(!equivalent("en, de;q=0.5", "en, de;q=0"));
+        assert!(!equivalent("en, de;q=0.5", "en"));
+    }
+
+    #[test]
+    fn it_returns_the_quality_of_an_accepted_language() {
+        assert_eq!(accepts(MOCK_ACCEPT_LANGUAGE, "de"), Some(0.7));
+        assert_eq!(accepts(MOCK_ACCEPT_LANGUAGE, "ZH-hant"), Some(1.0));
+        assert_eq!(accepts("de;q=0.2, fr, de;q=0.6", "de"), Some(0.6));
+        assert_eq!(accepts(MOCK_ACCEPT_LANGUAGE, "fr"), None);
+        assert_eq!(accepts("en, fr;q=0", "fr"), None);
+        assert_eq!(accepts("", ""), None);
+    }
+
+    #[test]
+    fn it_sorts_languages_like_the_parser() {
+        let mut languages = vec![
+            (String::from("jp"), 0.1),
+            (String::from("en-US"), 1.0),
+            (String::from("de"), 0.7),
+            (String::from("zh-Hant"), 1.0),
+        ];
+        sort_languages(&mut languages);
+        assert_eq!(
+            languages,
+            parse_with_quality("jp;q=0.1, en-US, de;q=0.7, zh-Hant")
+        );
+        let mut languages = vec![(String::from("en"), 0.5004), (String::from("de"), 0.5)];
+        sort_languages(&mut languages);
+        assert_eq!(languages[0].0, "en");
+    }
+
+    #[test]
+    fn it_returns_the_default_languages_in_the_intersection() {
+        assert_eq!(
+            intersection_defaults_only("nl, en-US;q=0.7", &["nl", "en-US"]),
+            vec!["nl"]
+        );
+        assert_eq!(intersection_defaults_only("en, en", &["en"]), vec!["en"]);
+        assert_eq!(
+            intersection_defaults_only("de;q=0.999, fr, en-US;q=1.0, it", &["de", "en-US", "fr"]),
+            vec!["fr", "en-US"]
+        );
+        assert!(intersection_defaults_only("de;q=0.5", &["de"]).is_empty());
+    }
+
+    #[test]
+    fn it_selects_a_language_with_its_quality() {
+        assert_eq!(
+            select_detailed(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES, "da"),
+            (String::from("en-US"), 1.0)
+        );
+        assert_eq!(
+            select_detailed(MOCK_ACCEPT_LANGUAGE, &["fr", "JP"], "da"),
+            (String::from("JP"), 0.1)
+        );
+        assert_eq!(
+            select_detailed("fr;q=0, it", &["fr"], "da"),
+            (String::from("da"), 0.0)
+        );
+        assert_eq!(
+            select_detailed(MOCK_ACCEPT_LANGUAGE, &["fr", "de", "JP"], "da").0,
+            select(MOCK_ACCEPT_LANGUAGE, &["fr", "de", "JP"], "da")
+        );
+    }
+
+    #[test]
+    fn it_finds_the_quality_parameter_by_name() {
+        for raw in &[
+            "en ; q = 0.5",
+            "en;;q=0.5",
+            "en;q=0.5;",
+            "en;level=1;q=0.5",
+            "en;Q=0.5",
+        ] {
+            assert_eq!(
+                Language::new(raw),
+                Language {
+                    name: String::from("en"),
+                    quality: Quality(500),
+                },
+                "{:?}",
+                raw
+            );
+        }
+        assert_eq!(
+            parse_with_quality("en;;q=0.5, de;level=1, fr;q=0.1;q=0.9"),
+            vec![
+                (String::from("de"), 1.0),
+                (String::from("en"), 0.5),
+                (String::from("fr"), 0.1)
+            ]
+        );
+        assert_eq!(Language::new("en;;;").quality, Quality::MAX);
+        assert_eq!(Language::new("en;qq=0.5").quality, Quality::MAX);
+    }
+
+    #[test]
+    fn it_parses_languages_with_their_position() {
+        assert_eq!(
+            parse_indexed("de;q=0.5, , en, de"),
+            vec![
+                (String::from("en"), 1.0, 1),
+                (String::from("de"), 1.0, 2),
+                (String::from("de"), 0.5, 0)
+            ]
+        );
+        let mut user_languages = parse_indexed(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(
+            user_languages
+                .iter()
+                .map(|l| l.0.clone())
+                .collect::<Vec<_>>(),
+            parse(MOCK_ACCEPT_LANGUAGE)
+        );
+        user_languages.sort_by_key(|l| l.2);
+        assert_eq!(
+            user_languages
+                .iter()
+                .map(|l| l.0.as_str())
+                .collect::<Vec<_>>(),
+            vec!["en-US", "de", "zh-Hant", "jp"]
+        );
+    }
+
+    #[test]
+    fn it_finds_the_closest_supported_language() {
+        let supported = &["de", "en-US", "en-GB", "zh-Hant"];
+        assert_eq!(closest("en-Us", supported), Some(String::from("en-US")));
+        assert_eq!(closest("en-UK", supported), Some(String::from("en-US")));
+        assert_eq!(
+            closest("en-GB-oxendict", supported),
+            Some(String::from("en-GB"))
+        );
+        assert_eq!(closest("de-AT-1996", supported), Some(String::from("de")));
+        assert_eq!(closest("zh-Hant-TW-x-a", supported), None);
+        assert_eq!(closest("fr-FR", supported), None);
+        assert_eq!(closest("eb-US", supported), Some(String::from("en-US")));
+        assert_eq!(closest("EB-gb", supported), Some(String::from("en-GB")));
+        assert_eq!(
+            closest("en-GV", &["de", "en-GB"]),
+            Some(String::from("en-GB"))
+        );
+        assert_eq!(
+            closest("eb-GV", &["de", "en-GB"]),
+            Some(String::from("en-GB"))
+        );
+        assert_eq!(closest("dee", supported), Some(String::from("de")));
+        assert_eq!(closest("zj-Hant", supported), Some(String::from("zh-Hant")));
+        assert_eq!(closest("ab-CD", supported), None);
+        assert!(is_typo_of("en", "EB"));
+        assert!(is_typo_of("de", "deu"));
+        assert!(is_typo_of("en", "en"));
+        assert!(!is_typo_of("fr", "de"));
+        assert!(!is_typo_of("en", "ne"));
+        assert!(!is_typo_of("e", "eng"));
+        assert_eq!(closest("x-pig-latin", &["x-pig-latn"]), None);
+        assert_eq!(closest("", supported), None);
+        assert_eq!(subtag_distance("en", "en-Latn-US"), 2);
+        assert_eq!(subtag_distance("en-US", "EN-us"), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "the supported languages must be sorted")]
+    fn it_panics_on_unsorted_supported_languages_in_debug_builds() {
+        intersection_ordered(MOCK_ACCEPT_LANGUAGE, &["en-US", "de"]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "the supported languages must be sorted")]
+    fn it_panics_on_unsorted_supported_languages_with_quality_in_debug_builds() {
+        intersection_ordered_with_quality(MOCK_ACCEPT_LANGUAGE, &["en-us", "en-US"]);
+    }
+
+    #[test]
+    fn it_explains_the_negotiated_language() {
+        let supported = &["de", "en", "zh-Hant"];
+        assert_eq!(
+            negotiate_explained("EN, de;q=0.5", supported, "de"),
+            (String::from("en"), NegotiationReason::ExactMatch)
+        );
+        assert_eq!(
+            negotiate_explained("zh-TW", supported, "de"),
+            (String::from("zh-Hant"), NegotiationReason::LossyMatch)
+        );
+        assert_eq!(
+            negotiate_explained("fr, *;q=0.5", supported, "de"),
+            (String::from("de"), NegotiationReason::Wildcard)
+        );
+        assert_eq!(
+            negotiate_explained("fr, *;q=0", supported, "de"),
+            (String::from("de"), NegotiationReason::FellBackToDefault)
+        );
+        assert_eq!(
+            negotiate_explained("", supported, "de"),
+            (String::from("de"), NegotiationReason::FellBackToDefault)
+        );
+    }
+
+    #[test]
+    fn it_parses_quoted_and_signed_qualities() {
+        assert_eq!(Language::quality_with_default("q=\"0.5\""), 0.5);
+        assert_eq!(Language::quality_with_default("q=+0.5"), 0.5);
+        assert_eq!(Language::quality_with_default("q=\"0.5"), 0.0);
+        assert_eq!(Language::quality_with_default("q=\"\"0.5\"\""), 0.0);
+        assert_eq!(Language::quality_with_default("q='0.5'"), 0.0);
+        assert_eq!(Language::quality_with_default("q=++0.5"), 0.0);
+        assert_eq!(
+            parse_with_quality("en;q=\"0.5\", de;q=+0.7"),
+            vec![(String::from("de"), 0.7), (String::from("en"), 0.5)]
+        );
+    }
+
+    #[test]
+    fn it_normalizes_the_languages_of_a_matcher() {
+        let matcher = Matcher::new(&["en_US", "en-US", "EN-us", "zh_hant_tw", "de", "fr-*"]);
+        assert_eq!(
+            matcher.supported_languages(),
+            &["en-US", "zh-Hant-TW", "de", "fr-*"]
+        );
+        assert_eq!(matcher.intersection("en-us"), vec!["en-US"]);
+        assert_eq!(
+            matcher.intersection("fr-CA, en_us;q=0.9, zh-Hant-TW;q=0.5, EN-US;q=0.4"),
+            vec!["fr-CA", "en-US", "zh-Hant-TW"]
+        );
+        assert!(Matcher::new(&[])
+            .intersection(MOCK_ACCEPT_LANGUAGE)
+            .is_empty());
+    }
+
+    #[test]
+    fn it_parses_into_a_fixed_size_buffer() {
+        let (user_languages, len) =
+            parse_fixed::<4>("jp;q=0.1, en-US, de;q=0.7, fr;q=0.7, zh-Hant, it;q=0.2");
+        assert_eq!(len, 4);
+        assert_eq!(
+            user_languages,
+            [
+                Some(("en-US", 1000)),
+                Some(("zh-Hant", 1000)),
+                Some(("de", 700)),
+                Some(("fr", 700))
+            ]
+        );
+        let (user_languages, len) = parse_fixed::<4>("de;q=0.5, en");
+        assert_eq!(len, 2);
+        assert_eq!(
+            user_languages,
+            [Some(("en", 1000)), Some(("de", 500)), None, None]
+        );
+        assert_eq!(parse_fixed::<0>(MOCK_ACCEPT_LANGUAGE), ([], 0));
+        let (user_languages, len) = parse_fixed::<8>(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(
+            user_languages[..len]
+                .iter()
+                .flatten()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<String>>(),
+            parse(MOCK_ACCEPT_LANGUAGE)
+        );
+    }
+
+    #[test]
+    fn it_negotiates_with_a_status() {
+        assert_eq!(
+            negotiate_status(None, AVIALABLE_LANGUAGES),
+            Negotiation::NoHeader
+        );
+        assert_eq!(
+            negotiate_status(Some("fr, nl;q=0.5"), AVIALABLE_LANGUAGES),
+            Negotiation::NoMatch
+        );
+        assert_eq!(
+            negotiate_status(Some(""), AVIALABLE_LANGUAGES),
+            Negotiation::NoMatch
+        );
+        assert_eq!(
+            negotiate_status(Some(MOCK_ACCEPT_LANGUAGE), AVIALABLE_LANGUAGES),
+            Negotiation::Matched(intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES))
+        );
+    }
+
+    #[test]
+    fn it_parses_qualities_as_decimals() {
+        assert_eq!(Language::new("en;q=0.1"), Language::new("en;q=0.100"));
+        assert_eq!(Language::new("en;q=0.1").quality, Quality(100));
+        assert_eq!(Language::new("en;q=0.1").quality.to_string(), "0.1");
+        assert_eq!(Language::new("en;q=0.100").quality.to_string(), "0.1");
+        assert_eq!(Language::parse_millis("0.9999"), Some((999, true)));
+        assert_eq!(Language::parse_millis("1.5"), Some((1000, false)));
+        assert_eq!(Language::parse_millis("-0.5"), Some((0, false)));
+        assert_eq!(Language::parse_millis(".5"), Some((500, false)));
+        assert_eq!(Language::parse_millis("1."), Some((1000, false)));
+        assert_eq!(Language::parse_millis("0.05"), Some((50, false)));
+        assert_eq!(Language::parse_millis("0.5e0"), None);
+        assert_eq!(Language::parse_millis("."), None);
+        assert_eq!(Language::parse_millis("0..5"), None);
+        assert_eq!(
+            parse_with_quality_typed("en;q=0.12345, de;q=0.1"),
+            vec![
+                (String::from("en"), Quality(123)),
+                (String::from("de"), Quality(100))
+            ]
+        );
+    }
+
+    #[test]
+    fn it_rejects_overlong_qualities_in_strict_mode() {
+        assert_eq!(
+            parse_strict("de, en;q=0.5000"),
+            Err(Error::InvalidQuality {
+                tag: String::from("en"),
+                quality: String::from("0.5000"),
+            })
+        );
+        assert_eq!(
+            parse_strict("de;q=0.100, en;q=0.1, de;q=0.1"),
+            Ok(vec![
+                String::from("de"),
+                String::from("en"),
+                String::from("de")
+            ])
+        );
+        assert_eq!(
+            Error::InvalidQuality {
+                tag: String::from("en"),
+                quality: String::from("0.5000"),
+            }
+            .to_string(),
+            "the language `en` has an invalid quality: 0.5000"
+        );
+    }
+
+    #[test]
+    fn it_checks_whether_the_default_is_acceptable() {
+        assert!(default_is_acceptable(MOCK_ACCEPT_LANGUAGE, "EN-us", 1.0));
+        assert!(default_is_acceptable("en-GB;q=0.9", "en", 0.5));
+        assert!(default_is_acceptable("zh-TW", "zh-Hant", 0.5));
+        assert!(!default_is_acceptable("de, en;q=0.3", "en", 0.5));
+        assert!(default_is_acceptable("de, en;q=0.3", "en", 0.3));
+        assert!(!default_is_acceptable("de, en;q=0", "en", 0.0));
+        assert!(!default_is_acceptable("de, fr", "en", 0.0));
+        assert!(!default_is_acceptable("en", "en-US", 0.0));
+    }
+
+    #[test]
+    fn it_negotiates_with_server_priorities() {
+        let server = &[("en", 1.0), ("de", 0.5), ("fr", 0.9)];
+        assert_eq!(select("de, en;q=0.8", &["en", "de", "fr"], "en"), "de");
+        assert_eq!(
+            negotiate_priorities("de, en;q=0.8", server),
+            Some(String::from("en"))
+        );
+        assert_eq!(
+            negotiate_priorities("DE, fr;q=0.5", server),
+            Some(String::from("de"))
+        );
+        assert_eq!(
+            negotiate_priorities("it, *;q=0.1", server),
+            Some(String::from("en"))
+        );
+        assert_eq!(
+            negotiate_priorities("fr;q=0.5, de", &[("fr", 1.0), ("de", 0.5)]),
+            Some(String::from("de"))
+        );
+        assert_eq!(
+            negotiate_priorities("en;q=0, *", server),
+            Some(String::from("fr"))
+        );
+        assert_eq!(negotiate_priorities("it", server), None);
+        assert_eq!(negotiate_priorities("en", &[("en", 0.0)]), None);
+    }
+
+    #[test]
+    fn it_ties_explicit_and_implicit_default_qualities() {
+        assert_eq!(parse("en, de;q=1, fr;q=0.5"), vec!["en", "de", "fr"]);
+        assert_eq!(parse("de;q=1, en, fr;q=0.5"), vec!["de", "en", "fr"]);
+        assert_eq!(parse("fr;q=0.5, de;q=1.000, en"), vec!["de", "en", "fr"]);
+        assert_eq!(
+            parse_with_quality_typed("en, de;q=1, fr;q=0.5"),
+            vec![
+                (String::from("en"), Quality::MAX),
+                (String::from("de"), Quality::MAX),
+                (String::from("fr"), Quality(500))
+            ]
+        );
+        assert_eq!(
+            intersection_defaults_only("en, de;q=1, fr;q=0.5", &["de", "en", "fr"]),
+            vec!["en", "de"]
+        );
+        assert!(equivalent("en, de;q=1, fr;q=0.5", "en;q=1, de, fr;q=0.5"));
+    }
+
+    #[test]
+    fn it_drops_qualities_in_order() {
+        let user_languages = parse_with_quality(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(tags_only(&user_languages), parse(MOCK_ACCEPT_LANGUAGE));
+        assert_eq!(
+            tag_refs(&user_languages),
+            vec!["en-US", "zh-Hant", "de", "jp"]
+        );
+        let reversed: Vec<(String, f32)> = user_languages.into_iter().rev().collect();
+        assert_eq!(tag_refs(&reversed), vec!["jp", "de", "zh-Hant", "en-US"]);
+        assert!(tags_only(&[]).is_empty());
+    }
+
+    #[test]
+    fn it_only_falls_back_to_a_base_language_with_the_same_script() {
+        assert_eq!(select_lossy("sr-Latn", &["sr"], "en"), "en");
+        assert_eq!(select_lossy("sr-Latn", &["sr", "sr-Latn"], "en"), "sr-Latn");
+        assert_eq!(select_lossy("sr-Cyrl-RS", &["sr"], "en"), "sr");
+        assert_eq!(select_lossy("sr-RS", &["sr"], "en"), "sr");
+        assert_eq!(select_lossy("zh-Hans-CN", &["zh"], "en"), "zh");
+        assert_eq!(select_lossy("en-Latn-US", &["en"], "de"), "en");
+        assert!(intersection_lossy("zh-Hant, zh-HK", &["zh"]).is_empty());
+        assert_eq!(
+            intersection_all_lossy("uz-Cyrl", &["uz", "uz-Cyrl"]),
+            vec!["uz-Cyrl"]
+        );
+        assert!(!default_is_acceptable("sr-Latn", "sr", 0.0));
+    }
+
+    #[test]
+    fn it_returns_language_intersection_and_unused_languages() {
+        assert_eq!(
+            intersection_and_unused("en, de", &["en", "de", "fr"]),
+            (
+                vec![String::from("en"), String::from("de")],
+                vec![String::from("fr")]
+            )
+        );
+        assert_eq!(
+            intersection_and_unused("en-US, it;q=0.5", &["fr", "en-*", "de-*", "it"]),
+            (
+                vec![String::from("en-US"), String::from("it")],
+                vec![String::from("fr"), String::from("de-*")]
+            )
+        );
+        let (_, unused_languages) = intersection_and_unused("", AVIALABLE_LANGUAGES);
+        assert_eq!(unused_languages, AVIALABLE_LANGUAGES);
+    }
 
     #[test]
-    fn it_returns_language_intersection() {
-        let common_languages = intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES);
+    fn it_parses_languages_in_header_order() {
+        assert_eq!(parse_unsorted("de;q=0.1, en"), vec!["de", "en"]);
         assert_eq!(
-            common_languages,
+            parse_unsorted(MOCK_ACCEPT_LANGUAGE),
+            vec!["en-US", "de", "zh-Hant", "jp"]
+        );
+        assert_eq!(parse_unsorted(" , it;q=0, fr ; q=0.5,"), vec!["fr"]);
+    }
+
+    #[test]
+    fn it_parses_language_tags_from_a_header() {
+        let user_languages = parse_language_tags("de;q=0.5, *;q=0.1, zh-Hant");
+        assert_eq!(
+            user_languages
+                .iter()
+                .map(LanguageTag::as_str)
+                .collect::<Vec<_>>(),
+            vec!["zh-Hant", "de", "*"]
+        );
+        assert_eq!(user_languages[0].script(), Some("Hant"));
+        assert_eq!(user_languages[1].language(), Some("de"));
+        assert!(!user_languages[1].is_wildcard());
+        assert!(user_languages[2].is_wildcard());
+        assert_eq!(user_languages[2].language(), None);
+    }
+
+    #[test]
+    fn it_returns_the_top_n_common_languages() {
+        let raw = "en-US, en-US;q=0.9, de;q=0.7, zh-Hant;q=0.5, fr;q=0.1";
+        assert_eq!(top_n(raw, AVIALABLE_LANGUAGES, 1), vec!["en-US"]);
+        assert_eq!(top_n(raw, AVIALABLE_LANGUAGES, 2), vec!["en-US", "de"]);
+        assert_eq!(
+            top_n(raw, AVIALABLE_LANGUAGES, 10),
+            vec!["en-US", "de", "zh-Hant"]
+        );
+        assert!(top_n(raw, AVIALABLE_LANGUAGES, 0).is_empty());
+    }
+
+    #[test]
+    fn it_flags_lossy_intersections() {
+        assert_eq!(
+            intersection_lossy_flagged(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            (
+                intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+                false
+            )
+        );
+        assert_eq!(
+            intersection_lossy_flagged("de, zh-TW;q=0.5", &["de", "zh-Hant"]),
+            (vec![String::from("de"), String::from("zh-Hant")], true)
+        );
+        assert_eq!(
+            intersection_lossy_flagged("de, de-CH;q=0.5", &["de"]),
+            (vec![String::from("de")], false)
+        );
+        assert_eq!(intersection_lossy_flagged("", &["de"]), (vec![], false));
+    }
+
+    #[test]
+    fn it_combines_user_and_server_quality_with_the_minimum() {
+        let supported = &[("en-US", 0.3), ("de", 1.0), ("ZH-hant", 0.9), ("fr", 0.0)];
+        assert_eq!(
+            intersection_min_quality(MOCK_ACCEPT_LANGUAGE, supported),
             vec![
-                String::from("en-US"),
-                String::from("zh-Hant"),
-                String::from("de"),
-                String::from("jp")
+                (String::from("zh-Hant"), 0.9),
+                (String::from("de"), 0.7),
+                (String::from("en-US"), 0.3)
             ]
-        )
+        );
+        assert_eq!(
+            intersection_min_quality("fr, en-US;q=0.3, de;q=0.3", supported),
+            vec![(String::from("en-US"), 0.3), (String::from("de"), 0.3)]
+        );
+        assert_eq!(intersection_min_quality("", supported), vec![]);
     }
 
     #[test]
-    fn it_returns_language_intersection_ordered() {
-        let common_languages = intersection_ordered(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES);
+    fn it_returns_the_top_quality() {
+        assert_eq!(top_quality(MOCK_ACCEPT_LANGUAGE), Some(1.0));
+        assert_eq!(top_quality("fr;q=0.2, en;q=0.1"), Some(0.2));
+        assert_eq!(top_quality("fr;q=0, en;q=0"), None);
+        assert_eq!(top_quality(""), None);
+        assert_eq!(top_quality(" , ;q=0.5"), None);
+    }
+
+    #[test]
+    fn it_matches_navigator_languages() {
+        for &(raw_languages, navigator) in NAVIGATOR_LANGUAGES {
+            assert!(
+                matches_navigator(raw_languages, navigator),
+                "{:?} didn't parse to {:?}",
+                raw_languages,
+                navigator
+            );
+        }
+        assert!(!matches_navigator("en-US,en;q=0.9", &["en", "en-US"]));
+    }
+
+    #[test]
+    fn it_negotiates_several_times_with_one_parsed_header() {
+        let accept_language = AcceptLanguage::parse(MOCK_ACCEPT_LANGUAGE);
+        assert_eq!(accept_language.languages(), parse(MOCK_ACCEPT_LANGUAGE));
+
+        let common_languages = accept_language.intersection(AVIALABLE_LANGUAGES);
         assert_eq!(
             common_languages,
+            intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES)
+        );
+        let supported: Vec<&str> = common_languages.iter().map(String::as_str).collect();
+        assert_eq!(accept_language.select(&supported[1..], "da"), "zh-Hant");
+        assert_eq!(
+            accept_language.lossy_intersection(&["de", "en", "zh"]),
+            intersection_lossy(MOCK_ACCEPT_LANGUAGE, &["de", "en", "zh"])
+        );
+        assert_eq!(accept_language.select(&[], "da"), "da");
+    }
+
+    #[test]
+    fn it_tells_rejections_from_invalid_qualities_in_strict_mode() {
+        assert_eq!(
+            parse_strict("de, en;q=0"),
+            Ok(vec![String::from("de"), String::from("en")])
+        );
+        assert_eq!(
+            parse_strict("de, en;q=bogus"),
+            Err(Error::InvalidQuality {
+                tag: String::from("en"),
+                quality: String::from("bogus")
+            })
+        );
+        assert_eq!(parse("de, en;q=bogus"), parse("de, en;q=0"));
+        assert_eq!(
+            parse_strict("de;q=0.5e0"),
+            Err(Error::InvalidQuality {
+                tag: String::from("de"),
+                quality: String::from("0.5e0")
+            })
+        );
+        for quality in &[
+            "\"0.5\"", "", "+0.5", ".5", "01", "-0", "0.5000", "1.5e0", "NaN",
+        ] {
+            assert_eq!(
+                parse_strict(&format!("de;q={}", quality)),
+                Err(Error::InvalidQuality {
+                    tag: String::from("de"),
+                    quality: quality.to_string()
+                }),
+                "{}",
+                quality
+            );
+        }
+        for (quality, value) in &[("2", 2.0), ("1.5", 1.5), ("-1", -1.0), ("1.0001", 1.0001)] {
+            assert_eq!(
+                parse_strict(&format!("de, en;q={}", quality)),
+                Err(Error::QualityOutOfRange { quality: *value }),
+                "{}",
+                quality
+            );
+        }
+        assert_eq!(parse_strict("de;q"), parse_strict("de;q="));
+        assert_eq!(parse_strict("de;q=1.000, en;q=0.001"), Ok(parse("de, en")));
+        assert_eq!(parse_strict("de;q=1., en;q=0."), Ok(parse("de, en;q=0")));
+    }
+
+    #[test]
+    fn it_negotiates_with_fallbacks() {
+        assert_eq!(
+            negotiate_with_fallbacks("fr-CA", &["en", "fr", "fr-CA"], "en"),
+            vec!["fr-CA", "fr", "en"]
+        );
+        assert_eq!(
+            negotiate_with_fallbacks("zh-Hant-TW", &["EN", "zh", "zh-hant-tw", "zh-Hant"], "en"),
+            vec!["zh-hant-tw", "zh-Hant", "zh", "en"]
+        );
+        assert_eq!(
+            negotiate_with_fallbacks("en-GB", &["en", "fr"], "en"),
+            vec!["en"]
+        );
+        assert_eq!(
+            negotiate_with_fallbacks("it", &["en", "fr"], "en"),
+            vec!["en"]
+        );
+    }
+
+    #[test]
+    fn it_maps_three_letter_codes_when_enabled() {
+        assert!(TWO_LETTER_CODES.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let matcher = Matcher::new(&["de", "de-CH", "en", "ast"]);
+        assert_eq!(matcher.intersection("deu, eng;q=0.5"), Vec::<String>::new());
+
+        let matcher = matcher.three_letter_codes(true);
+        assert_eq!(matcher.intersection("deu, eng;q=0.5"), vec!["de", "en"]);
+        assert_eq!(matcher.intersection("DEU-ch"), vec!["de-CH"]);
+        assert_eq!(matcher.intersection("ast, xyz"), vec!["ast"]);
+        assert_eq!(two_letter_code("xyz-CH"), None);
+    }
+
+    #[test]
+    fn it_parses_into_borrowed_tags_when_possible() {
+        let user_languages = parse_cow("en-US, en-us;q=0.5, zh-Hant-TW;q=0.7, ;q=0.9");
+        assert_eq!(user_languages, vec!["en-US", "zh-Hant-TW", "en-US"]);
+        assert!(matches!(user_languages[0], Cow::Borrowed("en-US")));
+        assert!(matches!(user_languages[1], Cow::Borrowed(_)));
+        assert!(matches!(user_languages[2], Cow::Owned(_)));
+
+        for tag in &[
+            "en",
+            "EN",
+            "zh-hant-tw",
+            "x-Priv",
+            "de-1996",
+            "sl-Latn-IT-rozaj",
+            "*",
+        ] {
+            assert_eq!(
+                is_canonical_case(tag),
+                canonical_case(tag) == *tag,
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn it_negotiates_with_a_configured_policy() {
+        let negotiator = Negotiator::new();
+        assert_eq!(
+            negotiator.negotiate(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES)
+        );
+        assert_eq!(negotiator.negotiate("EN-us", &["en-US"]), vec!["en-US"]);
+        assert_eq!(
+            negotiator
+                .clone()
+                .case_insensitive(false)
+                .negotiate("EN-us", &["en-US"]),
+            intersection("EN-us", &["en-US"])
+        );
+        assert_eq!(
+            negotiator
+                .clone()
+                .lossy(true)
+                .negotiate("en-US, de-CH;q=0.5, de;q=0", &["de", "en-us"]),
+            intersection_lossy("en-US, de-CH;q=0.5, de;q=0", &["de", "en-us"])
+        );
+        assert_eq!(
+            negotiator
+                .clone()
+                .min_quality(0.3)
+                .negotiate(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            tags_only(
+                &intersection_with_quality(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES)
+                    .into_iter()
+                    .filter(|(_, quality)| *quality >= 0.3)
+                    .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(
+            negotiator
+                .clone()
+                .server_order(true)
+                .negotiate(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            vec!["de", "en-US", "jp", "zh-Hant"]
+        );
+        assert_eq!(
+            negotiator.negotiate("de, *;q=0.5, fr;q=0", &["en", "de", "fr"]),
+            vec!["de"]
+        );
+        assert_eq!(
+            negotiator
+                .wildcard(true)
+                .negotiate("de, *;q=0.5, fr;q=0", &["en", "de", "fr"]),
+            vec!["de", "en"]
+        );
+    }
+
+    #[test]
+    fn it_parses_with_specificity_within_language() {
+        assert_eq!(
+            parse_specificity_within_language("zh, zh-Hant, zh-Hans"),
+            vec!["zh-Hant", "zh-Hans", "zh"]
+        );
+        assert_eq!(
+            parse_specificity_within_language("zh, en, ZH-hant-TW, en-GB, zh-Hans;q=0.5, zh;q=0.5"),
+            vec!["ZH-hant-TW", "zh", "en-GB", "en", "zh-Hans", "zh"]
+        );
+        assert_eq!(
+            parse_specificity_within_language(MOCK_ACCEPT_LANGUAGE),
+            parse(MOCK_ACCEPT_LANGUAGE)
+        );
+    }
+
+    #[test]
+    fn it_returns_the_same_negotiation_key_for_the_same_result() {
+        let supported = &["de", "en", "en-US"];
+        assert_eq!(
+            negotiation_key("en , de", supported),
+            negotiation_key("en,de", supported)
+        );
+        assert_eq!(
+            negotiation_key("en, it, de;q=0.5", supported),
+            negotiation_key("en,de;q=0.9", supported)
+        );
+        assert_ne!(
+            negotiation_key("en,de", supported),
+            negotiation_key("de,en", supported)
+        );
+        assert_ne!(
+            negotiation_key("en-US", supported),
+            negotiation_key("en", supported)
+        );
+        assert_eq!(
+            negotiation_key("", supported),
+            negotiation_key("it", supported)
+        );
+        assert_eq!(negotiation_key("", supported), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(negotiation_key("en", supported), 0xc2ef_bb18_f052_d5b2);
+    }
+
+    #[test]
+    fn it_never_matches_undetermined_to_a_language() {
+        assert_eq!(fallback_chain("und-Latn"), vec!["und-Latn", "und"]);
+        assert_eq!(
+            intersection_lossy("und, und-Latn", &["en"]),
+            Vec::<String>::new()
+        );
+        assert_eq!(intersection_lossy("und-Latn", &["en", "und"]), vec!["und"]);
+        assert_eq!(select_lossy("und-Latn", &["en"], "fr"), "fr");
+        assert_eq!(closest("und", &["en"]), None);
+    }
+
+    #[test]
+    fn it_returns_each_common_language_once() {
+        assert_eq!(intersection("en;q=0.9, en;q=0.4", &["en"]), vec!["en"]);
+        assert_eq!(
+            intersection_ordered("en;q=0.9, en;q=0.4", &["en"]),
+            vec!["en"]
+        );
+        assert_eq!(
+            AcceptLanguage::parse("en;q=0.9, en;q=0.4").intersection(&["en"]),
+            vec!["en"]
+        );
+        assert_eq!(
+            intersection("en-GB;q=0.4, de, en-GB, en-US", &["en-*", "de"]),
+            vec!["de", "en-GB", "en-US"]
+        );
+        assert_eq!(
+            intersection_ordered("de;q=0.5, en, DE", &["DE", "de"]),
+            vec!["DE", "de"]
+        );
+    }
+
+    #[test]
+    fn it_parses_primary_languages() {
+        assert_eq!(parse_primary("en-US, en-GB;q=0.5, de"), vec!["en", "de"]);
+        assert_eq!(
+            parse_primary("de;q=0.5, EN-gb;q=0.8, zh-Hant, en-US, x-klingon;q=0.1, *;q=0.1"),
+            vec!["zh", "en", "de", "x-klingon", "*"]
+        );
+        assert_eq!(parse_primary(""), Vec::<String>::new());
+        assert_eq!(
+            parse_primary("x-Foo, x-foo;q=0.5, X-FOO-bar"),
+            vec!["x-foo", "x-foo-bar"]
+        );
+    }
+
+    #[test]
+    fn it_negotiates_with_a_prior() {
+        let supported = &["en", "de", "fr"];
+        assert_eq!(
+            negotiate_with_prior("en, de;q=0.9", supported, "de", 0.2),
+            Some(String::from("de"))
+        );
+        assert_eq!(
+            negotiate_with_prior("en, de;q=0.9", supported, "de", 0.0),
+            Some(String::from("en"))
+        );
+        assert_eq!(
+            negotiate_with_prior("en, de;q=0.1", supported, "DE, fr;q=0.5", 0.2),
+            Some(String::from("en"))
+        );
+        assert_eq!(
+            negotiate_with_prior("it", supported, "fr", 2.0),
+            Some(String::from("fr"))
+        );
+        assert_eq!(
+            negotiate_with_prior("it, *;q=0.1", supported, "", 0.5),
+            Some(String::from("en"))
+        );
+        assert_eq!(negotiate_with_prior("it", supported, "", 0.5), None);
+    }
+
+    #[test]
+    fn it_validates_supported_languages_for_the_ordered_functions() {
+        assert!(is_valid_ordered(AVIALABLE_LANGUAGES));
+        assert!(is_valid_ordered(&[]));
+        assert!(!is_valid_ordered(&["zh", "de"]));
+        assert!(!is_valid_ordered(&["en-GB", "en-us"]));
+
+        let sorted = sort_supported(&["zh-hant", "en-us", "de", "en-US", "EN"]);
+        assert_eq!(sorted, vec!["de", "en", "en-US", "zh-Hant"]);
+        let sorted: Vec<&str> = sorted.iter().map(String::as_str).collect();
+        assert!(is_valid_ordered(&sorted));
+        assert_eq!(
+            intersection_ordered("en-US, zh-Hant", &sorted),
+            vec!["en-US", "zh-Hant"]
+        );
+    }
+
+    #[test]
+    fn it_parses_posix_locales() {
+        assert_eq!(parse_posix("en_US:de"), vec!["en-US", "de"]);
+        assert_eq!(parse_posix("en_US.UTF-8"), vec!["en-US"]);
+        assert_eq!(parse_posix("de_DE@euro"), vec!["de-DE"]);
+        assert_eq!(parse_posix("sr_RS.UTF-8@latin:C::POSIX"), vec!["sr-RS"]);
+        assert_eq!(parse_posix(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_counts_the_common_languages() {
+        assert_eq!(
+            intersection_count(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            intersection(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES).len()
+        );
+        assert_eq!(
+            intersection_count(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            4
+        );
+        assert_eq!(
+            intersection_count("en-GB, fr;q=0.5, en-US", &["en-*", "fr"]),
+            3
+        );
+        assert_eq!(intersection_count("fr, es", AVIALABLE_LANGUAGES), 0);
+        assert_eq!(intersection_count("", AVIALABLE_LANGUAGES), 0);
+    }
+
+    #[test]
+    fn it_rejects_segments_that_are_not_language_tags_in_strict_mode() {
+        for junk in &["123", "!!!", "verylonggarbagetoken", "-US", "ü"] {
+            assert_eq!(
+                parse_strict(&format!("en, {};q=0.5", junk)),
+                Err(Error::NotALanguageTag {
+                    tag: junk.to_string()
+                })
+            );
+            assert_eq!(parse(&format!("en, {};q=0.5", junk)), vec!["en", junk]);
+        }
+        assert_eq!(
+            parse_strict("en, x-private, *, und-Latn, i-klingon"),
+            Ok(parse("en, x-private, *, und-Latn, i-klingon"))
+        );
+    }
+
+    #[test]
+    fn it_filters_languages_keeping_their_order() {
+        let user_languages = parse_with_quality(MOCK_ACCEPT_LANGUAGE);
+        let cached = ["jp", "en-US", "de"];
+        assert_eq!(
+            filter_languages(&user_languages, |tag| cached.contains(&tag)),
+            vec![("en-US", 1.0), ("de", 0.7), ("jp", 0.1)]
+        );
+        assert_eq!(
+            retain_languages(user_languages, |tag| cached.contains(&tag)),
             vec![
-                String::from("en-US"),
-                String::from("zh-Hant"),
-                String::from("de"),
-                String::from("jp")
+                (String::from("en-US"), 1.0),
+                (String::from("de"), 0.7),
+                (String::from("jp"), 0.1)
             ]
-        )
+        );
+        assert_eq!(retain_languages(vec![], |_| true), vec![]);
     }
 
     #[test]
-    fn it_returns_language_intersection_with_quality() {
-        let common_languages = intersection_with_quality(MOCK_ACCEPT_LANGUAGE, &["en-US", "jp"]);
+    fn it_handles_long_subtag_chains() {
+        // The chains are compared by length, since printing them on failure would be quadratic.
+        let tag = vec!["a"; 100_000].join("-");
+        assert_eq!(fallback_chain(&tag).len(), 50_000);
+        let tag = vec!["ab"; 100_000].join("-");
+        let chain = fallback_chain(&tag);
+        assert_eq!(chain.len(), 100_000);
+        assert_eq!(chain.last(), Some(&"ab"));
+        assert!(parse(&tag) == [tag.as_str()]);
+        assert_eq!(intersection_lossy(&tag, &["ab"]), vec!["ab"]);
+
+        let tag = format!("{}-Latn-US", vec!["ab"; 100_000].join("-"));
+        assert_eq!(fallback_chain(&tag).len(), 100_002);
+        assert_eq!(parse_primary(&tag), vec!["ab"]);
+    }
+
+    #[test]
+    #[ignore = "times work on long inputs, run with --ignored"]
+    fn it_scales_linearly_with_long_subtag_chains() {
+        // Four times the subtags take about four times as long in linear time, and sixteen
+        // times in quadratic time. The fastest of a few runs keeps scheduling noise out.
+        let fastest = |subtags: usize| {
+            let tag = vec!["ab"; subtags].join("-");
+            (0..5)
+                .map(|_| {
+                    let start = Instant::now();
+                    fallback_chain(&tag);
+                    intersection_lossy(&tag, &["ab"]);
+                    LanguageTag::parse(&tag).canonical();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+                .as_secs_f64()
+        };
+        let growth = fastest(100_000) / fastest(25_000);
+        assert!(growth < 10.0, "4x the subtags took {:.1}x as long", growth);
+    }
+
+    #[test]
+    fn it_checks_whether_a_tag_is_region_specific() {
+        assert!(!is_region_specific("en"));
+        assert!(is_region_specific("en-US"));
+        assert!(!is_region_specific("zh-Hant"));
+        assert!(is_region_specific("zh-Hant-TW"));
+        assert!(is_region_specific("es-419"));
+        assert!(!is_region_specific("de-1996"));
+        assert!(!is_region_specific("x-us"));
+        assert!(!is_region_specific("*"));
+    }
+
+    #[test]
+    fn it_orders_the_supported_languages_for_a_switcher() {
         assert_eq!(
-            common_languages,
-            vec![(String::from("en-US"), 1.0), (String::from("jp"), 0.1)]
-        )
+            switcher_order("fr, en;q=0.5", &["de", "en", "fr"]),
+            vec!["fr", "en", "de"]
+        );
+        assert_eq!(
+            switcher_order(
+                "EN, it;q=0, fr;q=0.5, en;q=0.9",
+                &["it", "fr", "de", "en", "Da", "de"]
+            ),
+            vec!["en", "fr", "Da", "de", "it"]
+        );
+        assert_eq!(switcher_order("", &["fr", "de"]), vec!["de", "fr"]);
     }
 
     #[test]
-    fn it_returns_language_intersection_ordered_with_quality() {
+    fn it_parses_within_bounds() {
+        let raw_languages = "en-US, de-CH;q=0.5, de;q=0.4, zh-Hant-TW;q=0.1";
+        assert_eq!(
+            parse_bounded(raw_languages, 4, 12),
+            vec!["en-US", "de-CH", "de"]
+        );
+        assert_eq!(parse_bounded(raw_languages, 4, 11), vec!["en-US", "de-CH"]);
+        assert_eq!(parse_bounded(raw_languages, 4, 9), vec!["en-US"]);
+        assert_eq!(parse_bounded(raw_languages, 1, 100), vec!["en-US"]);
+        assert_eq!(parse_bounded(raw_languages, 10, 100), parse(raw_languages));
+        assert_eq!(parse_bounded(raw_languages, 0, 100), Vec::<String>::new());
+        assert_eq!(parse_bounded(raw_languages, 10, 0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_compares_the_languages_of_two_headers() {
+        assert_eq!(
+            header_diff("en, de", "en, fr"),
+            (vec![String::from("de")], vec![String::from("fr")])
+        );
+        assert_eq!(
+            header_diff("en-us, de;q=0.5, it;q=0", "en-US;q=0.1, it, DE, de;q=0"),
+            (vec![], vec![String::from("it")])
+        );
+        assert_eq!(
+            header_diff("fr;q=0.2, es", ""),
+            (vec![String::from("es"), String::from("fr")], vec![])
+        );
+    }
+
+    #[test]
+    fn it_rescales_the_qualities_to_the_top_one() {
+        assert_eq!(
+            parse_rescaled_top("de;q=0.8, en;q=0.4"),
+            vec![(String::from("de"), 1.0), (String::from("en"), 0.5)]
+        );
+        assert_eq!(
+            parse_rescaled_top(MOCK_ACCEPT_LANGUAGE),
+            parse_with_quality(MOCK_ACCEPT_LANGUAGE)
+        );
+        assert_eq!(
+            parse_rescaled_top("de;q=0.3, en;q=0.1, fr;q=0"),
+            vec![
+                (String::from("de"), 1.0),
+                (String::from("en"), 0.333),
+                (String::from("fr"), 0.0)
+            ]
+        );
+        assert_eq!(
+            parse_rescaled_top("de;q=0, en;q=0"),
+            vec![(String::from("de"), 0.0), (String::from("en"), 0.0)]
+        );
+        assert_eq!(parse_rescaled_top(""), vec![]);
+    }
+
+    #[test]
+    fn it_groups_the_common_languages_by_quality() {
+        assert_eq!(
+            intersection_tiers("en-US, en-GB, de;q=0.5", &["en-US", "en-GB", "de"]),
+            vec![
+                (1.0, vec![String::from("en-US"), String::from("en-GB")]),
+                (0.5, vec![String::from("de")])
+            ]
+        );
+        assert_eq!(
+            intersection_tiers(MOCK_ACCEPT_LANGUAGE, AVIALABLE_LANGUAGES),
+            vec![
+                (1.0, vec![String::from("en-US"), String::from("zh-Hant")]),
+                (0.7, vec![String::from("de")]),
+                (0.1, vec![String::from("jp")])
+            ]
+        );
+        assert_eq!(intersection_tiers("de;q=0, fr", &["de"]), vec![]);
+    }
+
+    #[test]
+    fn it_skips_empty_segments() {
+        assert_eq!(parse(",en-US,,de,"), vec!["en-US", "de"]);
+        assert_eq!(parse(",,,"), Vec::<String>::new());
+        assert_eq!(parse(" , \t,;q=0.5, en"), vec!["en"]);
+        assert_eq!(split_languages(",en-US,,de,").len(), 2);
+        assert!(split_languages(",,,").is_empty());
+        assert_eq!(parse_strict(",en-US,,de,"), Ok(parse("en-US, de")));
+    }
+
+    #[test]
+    fn it_negotiates_against_layered_sets() {
+        let tenant: &[&str] = &["it", "fr"];
+        let global: &[&str] = &["en", "de", "fr"];
+        assert_eq!(
+            negotiate_layered("de, en;q=0.5", &[tenant, global], "en"),
+            "de"
+        );
+        assert_eq!(
+            negotiate_layered("de, FR;q=0.5", &[tenant, global], "en"),
+            "fr"
+        );
+        assert_eq!(
+            negotiate_layered("de, it;q=0", &[tenant, global], "en"),
+            "de"
+        );
+        assert_eq!(negotiate_layered("es", &[tenant, global], "en"), "en");
+        assert_eq!(negotiate_layered("de", &[], "en"), "en");
+    }
+
+    #[test]
+    fn it_flags_the_languages_with_the_default_quality() {
+        assert_eq!(
+            parse_with_default_flag("nl, en-US;q=0.7"),
+            vec![
+                (String::from("nl"), 1.0, true),
+                (String::from("en-US"), 0.7, false)
+            ]
+        );
+        assert_eq!(
+            parse_with_default_flag("de;q=0.999, en;q=1.000, fr;q=0"),
+            vec![
+                (String::from("en"), 1.0, true),
+                (String::from("de"), 0.999, false),
+                (String::from("fr"), 0.0, false)
+            ]
+        );
+        assert_eq!(parse_with_default_flag(""), vec![]);
+    }
+
+    #[test]
+    fn it_parses_leniently() {
+        assert_eq!(
+            parse_lenient("En_us ; Q = +.5"),
+            vec![(String::from("en-US"), 0.5)]
+        );
+        assert_eq!(
+            parse_lenient("\tzh_hant_tw;level=1; q=\"0.8\" ,, de ; q = 1. , EN-us;q=0.9, fr;q=x"),
+            vec![
+                (String::from("de"), 1.0),
+                (String::from("en-US"), 0.9),
+                (String::from("zh-Hant-TW"), 0.8),
+                (String::from("fr"), 0.0)
+            ]
+        );
+        assert_eq!(
+            parse_lenient("en\r\n -GB;q="),
+            vec![(String::from("en-GB"), 1.0)]
+        );
+        assert_eq!(parse_lenient(" , ;q=0.5"), vec![]);
+    }
+
+    #[test]
+    fn it_expands_the_wildcard() {
+        let supported = &["de", "en-US", "fr", "it-*"];
+        assert_eq!(parse("en-US, *;q=0.5"), vec!["en-US", "*"]);
+        assert_eq!(
+            intersection_with_wildcard("en-US, *;q=0.5", supported, Wildcard::Ignore),
+            intersection("en-US, *;q=0.5", supported)
+        );
+        assert_eq!(
+            intersection_with_wildcard("it-CH;q=0.1, en-US, *;q=0.5", supported, Wildcard::All),
+            vec!["en-US", "de", "fr", "it-CH"]
+        );
+        assert_eq!(
+            intersection_with_wildcard("*;q=0.5, en-US, FR;q=0", supported, Wildcard::All),
+            vec!["en-US", "de"]
+        );
+        assert_eq!(
+            intersection_with_wildcard("es, *;q=0.5", supported, Wildcard::Only(&["FR", "es"])),
+            vec!["fr"]
+        );
+        assert_eq!(
+            intersection_with_wildcard("es, *;q=0", supported, Wildcard::All),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn it_filters_with_basic_filtering() {
+        let supported = &[
+            "de",
+            "de-CH",
+            "de-DE-1996",
+            "dex",
+            "en-US",
+            "x-pig",
+            "x-pig-latin",
+        ];
+        assert_eq!(
+            basic_filter("DE", supported),
+            vec!["de", "de-CH", "de-DE-1996"]
+        );
+        assert_eq!(
+            basic_filter("de-ch;q=0.9, en, de;q=0.5", supported),
+            vec!["en-US", "de-CH", "de", "de-DE-1996"]
+        );
+        assert_eq!(basic_filter("x-pig", supported), vec!["x-pig"]);
+        assert_eq!(basic_filter("*;q=0.1, x-pig", supported)[0], "x-pig");
+        assert_eq!(basic_filter("*;q=0.1", supported).len(), supported.len());
+        assert_eq!(basic_filter("de;q=0, fr", supported), Vec::<String>::new());
+    }
+
+    #[test]
+    fn it_looks_up_the_best_language() {
+        let supported = &["de", "en", "zh", "zh-Hant", "x-pig-latin"];
+        assert_eq!(
+            lookup("zh-Hant-CN-x-private", supported),
+            Some(String::from("zh-Hant"))
+        );
+        assert_eq!(lookup("zh-Hans-CN", supported), Some(String::from("zh")));
+        assert_eq!(
+            lookup("fr-CA, DE-ch;q=0.5, en;q=0.9", supported),
+            Some(String::from("en"))
+        );
+        assert_eq!(
+            lookup("en-US;q=0, de-AT;q=0.1", supported),
+            Some(String::from("de"))
+        );
+        assert_eq!(
+            lookup("x-pig-latin", supported),
+            Some(String::from("x-pig-latin"))
+        );
+        assert_eq!(lookup("x-pig", supported), None);
+        assert_eq!(lookup("fr, *;q=0.5", supported), None);
+        assert_eq!(lookup("", supported), None);
+    }
+
+    #[test]
+    fn it_dedupes_long_headers() {
+        let raw_languages: Vec<String> = (0..20_000).map(|i| format!("x-{};q=0.5", i)).collect();
+        let raw_languages = format!("{}, X-0", raw_languages.join(", "));
+        let languages = parse_with_quality(&raw_languages);
+        assert_eq!(languages.len(), 20_000);
+        assert_eq!(languages[0], (String::from("X-0"), 1.0));
+        assert_eq!(languages[1], (String::from("x-1"), 0.5));
+    }
+
+    #[test]
+    fn it_dedupes_long_supported_lists() {
+        let supported: Vec<String> = (0..20_000).map(|i| format!("x_{}", i)).collect();
+        let mut supported: Vec<&str> = supported.iter().map(String::as_str).collect();
+        supported.extend(["X-0", "x-1"]);
+        let matcher = Matcher::new(&supported);
+        assert_eq!(matcher.supported_languages().len(), 20_000);
+        assert_eq!(matcher.supported_languages()[1], "x-1");
+    }
+
+    #[test]
+    fn it_matches_the_wildcard_with_quality() {
+        let supported = &["de", "en-US", "fr"];
         let common_languages =
-            intersection_ordered_with_quality(MOCK_ACCEPT_LANGUAGE, &["en-US", "jp"]);
+            intersection_with_quality_wildcard("fr, *;q=0.5, de;q=0", supported, Wildcard::All);
         assert_eq!(
             common_languages,
-            vec![(String::from("en-US"), 1.0), (String::from("jp"), 0.1)]
-        )
+            vec![(String::from("fr"), 1.0), (String::from("en-US"), 0.5)]
+        );
+        let common_languages =
+            intersection_with_quality_wildcard("fr, *;q=0.5", supported, Wildcard::Ignore);
+        assert_eq!(common_languages, vec![(String::from("fr"), 1.0)]);
     }
 
     #[test]
-    fn it_returns_an_empty_array_when_no_intersection() {
-        let common_languages = intersection(MOCK_ACCEPT_LANGUAGE, &["fr", "en-GB"]);
-        assert_eq!(common_languages.len(), 0)
+    fn it_matches_the_wildcard_lossily() {
+        let supported = &["de", "en", "fr"];
+        let common_languages =
+            intersection_lossy_wildcard("en-US, *;q=0.5, de;q=0", supported, Wildcard::All);
+        assert_eq!(common_languages, vec!["en", "fr"]);
+        let common_languages =
+            intersection_lossy_wildcard("it-CH, *;q=0.5", supported, Wildcard::Only(&["fr"]));
+        assert_eq!(common_languages, vec!["fr"]);
+        assert_eq!(
+            intersection_lossy_wildcard("de-AT, *;q=0.5", supported, Wildcard::Ignore),
+            intersection_lossy("de-AT, *;q=0.5", supported)
+        );
     }
 
     #[test]
-    fn it_parses_traditional_chinese() {
-        assert_eq!(parse("zh-Hant"), &["zh-Hant"]);
+    fn it_counts_malformed_headers_like_intersection() {
+        let supported = &["en", "en-US", "de"];
+        for header in &[
+            "e n",
+            "en -US, de",
+            "e n, en",
+            "en -US, en-US;q=0.5",
+            "en\r\n -US, de",
+            "de,, d e;q=0.5, ;q=0.3",
+            "en-US ; q = 0.5, d\r\n\te",
+        ] {
+            assert_eq!(
+                intersection_count(header, supported),
+                intersection(header, supported).len(),
+                "{:?}",
+                header
+            );
+        }
     }
 
     #[test]
-    fn it_implements_case_insensitive_equality() {
-        assert_eq!(Language::new("en-US"), Language::new("en-us"));
-        assert_eq!(Language::new("en-US;q=0.7"), Language::new("en-us;q=0.7"));
-        assert_ne!(Language::new("en"), Language::new("en-US"));
-        assert_ne!(Language::new("en;q=0.7"), Language::new("en;q=0.8"));
-        assert_ne!(Language::new("en;q=0.7"), Language::new("en-US;q=0.7"));
+    fn it_accepts_tags_with_spaces_like_parse() {
+        assert_eq!(parse("e n"), vec!["en"]);
+        assert_eq!(accepts("e n", "en"), Some(1.0));
+        assert_eq!(accepts("de, e n -US;q=0.5", "en-us"), Some(0.5));
+        assert_eq!(accepts("e\r\n n;q=0.3", "en"), Some(0.3));
+        assert_eq!(accepts("e n;q=0", "en"), None);
     }
 }
@@ -0,0 +1,383 @@
+//! A structured view of a single language tag, for region- or script-aware logic without
+//! splitting tags by hand.
+use std::fmt;
+
+/// A language tag like `zh-Hant-TW`, or the `*` range that stands for any language.
+///
+/// The subtags are classified by their shape as described in
+/// [RFC 5646](https://www.rfc-editor.org/rfc/rfc5646#section-2.1): the language comes first, a
+/// script is four letters and a region is two letters or three digits, while an extended
+/// language is three letters and a variant is five to eight characters, or four starting with a
+/// digit. Extensions and private use sequences follow a single-letter subtag. Scripts and
+/// regions are recognized in any order, since some clients send malformed tags like
+/// `zh-TW-Hant`, and [`canonical`](LanguageTag::canonical) puts them back in order. The tag is
+/// kept as it was given, so nothing is lost for tags that don't follow that shape.
+///
+/// # Example
+///
+/// ```
+/// use accept_language::LanguageTag;
+///
+/// let tag = LanguageTag::parse("zh-Hant-TW");
+/// assert_eq!(tag.language(), Some("zh"));
+/// assert_eq!(tag.script(), Some("Hant"));
+/// assert_eq!(tag.region(), Some("TW"));
+/// assert!(LanguageTag::parse("*").is_wildcard());
+///
+/// let tag = LanguageTag::parse("zh-yue-Hant-HK-pinyin-u-nu-hanidec-x-private");
+/// assert_eq!(tag.extended_language(), Some("yue"));
+/// assert_eq!(tag.variants(), vec!["pinyin"]);
+/// assert_eq!(tag.extensions(), vec!["u-nu-hanidec"]);
+/// assert_eq!(tag.private_use(), Some("x-private"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LanguageTag {
+    tag: String,
+}
+
+impl LanguageTag {
+    /// Parse a single language tag, ignoring surrounding whitespace. This never fails, since a
+    /// tag that doesn't follow the expected shape only has fewer of its subtags classified.
+    pub fn parse(tag: &str) -> LanguageTag {
+        LanguageTag {
+            tag: tag.trim().to_string(),
+        }
+    }
+
+    /// The tag as it was given.
+    pub fn as_str(&self) -> &str {
+        &self.tag
+    }
+
+    /// Check whether this is the `*` range, which matches any language. It has no subtags.
+    pub fn is_wildcard(&self) -> bool {
+        self.tag == "*"
+    }
+
+    /// The primary language subtag, like `zh` for `zh-Hant-TW`. The wildcard and private use
+    /// tags like `x-klingon` don't have one.
+    pub fn language(&self) -> Option<&str> {
+        self.language_subtags().next()
+    }
+
+    /// Check whether the primary language is `und`, which BCP 47 uses for an undetermined
+    /// language, like in `und-Latn` for some text in the Latin script.
+    pub fn is_undetermined(&self) -> bool {
+        self.language()
+            .is_some_and(|language| language.eq_ignore_ascii_case("und"))
+    }
+
+    /// The script subtag, like `Hant` for `zh-Hant-TW` or `zh-TW-Hant`.
+    pub fn script(&self) -> Option<&str> {
+        self.language_subtags()
+            .skip(1)
+            .find(|subtag| is_script(subtag))
+    }
+
+    /// The region subtag, like `TW` for `zh-Hant-TW` or `419` for `es-419`.
+    pub fn region(&self) -> Option<&str> {
+        self.language_subtags()
+            .skip(1)
+            .find(|subtag| is_region(subtag))
+    }
+
+    /// The extended language subtag, like `yue` for `zh-yue-HK`. It can only come right after a
+    /// primary language of two or three letters, so the `abc` in `en-US-abc` isn't one.
+    pub fn extended_language(&self) -> Option<&str> {
+        let mut subtags = self.language_subtags();
+        let language = subtags.next()?;
+        subtags
+            .next()
+            .filter(|subtag| language.len() <= 3 && is_extended_language(subtag))
+    }
+
+    /// The variant subtags, like `rozaj` and `1994` for `sl-rozaj-1994`, in the order they were
+    /// given.
+    pub fn variants(&self) -> Vec<&str> {
+        self.language_subtags()
+            .skip(1)
+            .filter(|subtag| is_variant(subtag))
+            .collect()
+    }
+
+    /// The extensions, each with its single-letter subtag, like `u-ca-buddhist` for
+    /// `th-u-ca-buddhist`. The private use sequence isn't an extension.
+    pub fn extensions(&self) -> Vec<&str> {
+        self.singleton_sequences()
+            .into_iter()
+            .filter(|sequence| !is_private_use(sequence))
+            .collect()
+    }
+
+    /// The private use sequence, like `x-private` for `en-x-private`. It's the whole tag for a
+    /// private use tag like `x-klingon`.
+    pub fn private_use(&self) -> Option<&str> {
+        self.singleton_sequences()
+            .into_iter()
+            .find(|sequence| is_private_use(sequence))
+    }
+
+    /// The tag in the canonical form of [BCP 47](https://www.rfc-editor.org/rfc/rfc5646): the
+    /// script before the region, with the usual casing, so `ZH-tw-hant` becomes `zh-Hant-TW`.
+    /// An extended language stays right after the language, and the other subtags keep their
+    /// order after the region, followed by any extensions.
+    pub fn canonical(&self) -> String {
+        if self.is_wildcard() {
+            return self.tag.clone();
+        }
+        let extended_language = self.extended_language();
+        let (script, region) = (self.script(), self.region());
+        let script_index = self.language_subtags().skip(1).position(is_script);
+        let region_index = self.language_subtags().skip(1).position(is_region);
+        let mut subtags: Vec<&str> = self.language().into_iter().collect();
+        subtags.extend(extended_language);
+        subtags.extend(script);
+        subtags.extend(region);
+        subtags.extend(
+            self.language_subtags()
+                .skip(1)
+                .enumerate()
+                .filter(|&(i, _)| {
+                    !(i == 0 && extended_language.is_some())
+                        && Some(i) != script_index
+                        && Some(i) != region_index
+                })
+                .map(|(_, subtag)| subtag),
+        );
+        subtags.extend(self.tag.split('-').skip_while(|subtag| subtag.len() > 1));
+        crate::canonical_case(&subtags.join("-"))
+    }
+
+    /// The sequences starting with a single-letter subtag, each up to the next one, except that
+    /// a private use sequence runs to the end of the tag. A tag can only start with the `x` of
+    /// private use, so the `i` of a grandfathered tag like `i-klingon` doesn't start one.
+    fn singleton_sequences(&self) -> Vec<&str> {
+        let mut sequences = Vec::new();
+        if self.is_wildcard() {
+            return sequences;
+        }
+        let mut start = None;
+        let mut offset = 0;
+        for subtag in self.tag.split('-') {
+            if subtag.len() == 1 && (offset > 0 || is_private_use(subtag)) {
+                if let Some(start) = start {
+                    sequences.push(&self.tag[start..offset - 1]);
+                }
+                start = Some(offset);
+                if is_private_use(subtag) {
+                    break;
+                }
+            }
+            offset += subtag.len() + 1;
+        }
+        if let Some(start) = start {
+            sequences.push(&self.tag[start..]);
+        }
+        sequences
+    }
+
+    /// The subtags before the first single-letter subtag, which starts an extension or a
+    /// private use sequence. There are none for the wildcard.
+    fn language_subtags(&self) -> impl Iterator<Item = &str> {
+        let subtags = if self.is_wildcard() {
+            ""
+        } else {
+            &self.tag[..]
+        };
+        subtags.split('-').take_while(|subtag| subtag.len() > 1)
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.tag)
+    }
+}
+
+/// A script subtag is four letters, like `Latn`.
+fn is_script(subtag: &str) -> bool {
+    subtag.len() == 4 && subtag.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// An extended language subtag is three letters, like `yue`.
+fn is_extended_language(subtag: &str) -> bool {
+    subtag.len() == 3 && subtag.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+/// A variant subtag is five to eight letters or digits, like `rozaj`, or four starting with a
+/// digit, like `1996`.
+fn is_variant(subtag: &str) -> bool {
+    let alphanumeric = subtag.bytes().all(|b| b.is_ascii_alphanumeric());
+    alphanumeric
+        && ((5..=8).contains(&subtag.len())
+            || (subtag.len() == 4 && subtag.as_bytes()[0].is_ascii_digit()))
+}
+
+/// A private use sequence starts with `x`, like `x-private`.
+fn is_private_use(sequence: &str) -> bool {
+    sequence
+        .split('-')
+        .next()
+        .is_some_and(|singleton| singleton.eq_ignore_ascii_case("x"))
+}
+
+/// A region subtag is two letters, like `US`, or three digits, like `419`.
+fn is_region(subtag: &str) -> bool {
+    (subtag.len() == 2 && subtag.bytes().all(|b| b.is_ascii_alphabetic()))
+        || (subtag.len() == 3 && subtag.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LanguageTag;
+
+    #[test]
+    fn it_parses_a_wildcard_tag() {
+        let tag = LanguageTag::parse(" * ");
+        assert!(tag.is_wildcard());
+        assert_eq!(tag.as_str(), "*");
+        assert_eq!(tag.language(), None);
+        assert_eq!(tag.script(), None);
+        assert_eq!(tag.region(), None);
+    }
+
+    #[test]
+    fn it_parses_language_tags() {
+        let tag = LanguageTag::parse("en");
+        assert!(!tag.is_wildcard());
+        assert_eq!(tag.language(), Some("en"));
+        assert_eq!(tag.script(), None);
+        assert_eq!(tag.region(), None);
+
+        let tag = LanguageTag::parse("es-419");
+        assert_eq!(tag.language(), Some("es"));
+        assert_eq!(tag.region(), Some("419"));
+
+        let tag = LanguageTag::parse("sr-Latn-RS-u-nu-latn");
+        assert_eq!(tag.to_string(), "sr-Latn-RS-u-nu-latn");
+        assert_eq!(tag.script(), Some("Latn"));
+        assert_eq!(tag.region(), Some("RS"));
+
+        let tag = LanguageTag::parse("x-klingon");
+        assert_eq!(tag.language(), None);
+        assert_eq!(tag.region(), None);
+
+        assert_eq!(LanguageTag::parse("de-1996").region(), None);
+        assert_eq!(LanguageTag::parse("").language(), None);
+    }
+
+    #[test]
+    fn it_classifies_subtags_by_shape() {
+        let tag = LanguageTag::parse("zh-TW-Hant");
+        assert_eq!(tag.language(), Some("zh"));
+        assert_eq!(tag.script(), Some("Hant"));
+        assert_eq!(tag.region(), Some("TW"));
+        assert_eq!(tag.canonical(), "zh-Hant-TW");
+        assert_eq!(tag.as_str(), "zh-TW-Hant");
+
+        assert_eq!(LanguageTag::parse("ZH-hant-tw").canonical(), "zh-Hant-TW");
+        assert_eq!(LanguageTag::parse("zh-yue-HK").canonical(), "zh-yue-HK");
+        assert_eq!(
+            LanguageTag::parse("sl-IT-rozaj-Latn-x-Priv").canonical(),
+            "sl-Latn-IT-rozaj-x-priv"
+        );
+        assert_eq!(LanguageTag::parse("de-1996").canonical(), "de-1996");
+        assert_eq!(LanguageTag::parse("en-us-US").canonical(), "en-US-US");
+        assert_eq!(LanguageTag::parse("x-klingon").canonical(), "x-klingon");
+        assert_eq!(LanguageTag::parse("*").canonical(), "*");
+    }
+
+    #[test]
+    fn it_parses_undetermined_tags() {
+        let tag = LanguageTag::parse("und");
+        assert_eq!(tag.language(), Some("und"));
+        assert!(tag.is_undetermined());
+
+        let tag = LanguageTag::parse("UND-latn");
+        assert_eq!(tag.script(), Some("latn"));
+        assert_eq!(tag.canonical(), "und-Latn");
+        assert!(tag.is_undetermined());
+
+        assert!(!LanguageTag::parse("en").is_undetermined());
+        assert!(!LanguageTag::parse("*").is_undetermined());
+        assert!(!LanguageTag::parse("x-und").is_undetermined());
+    }
+
+    #[test]
+    fn it_classifies_long_subtag_chains() {
+        let tag = LanguageTag::parse(&format!("{}-Latn-US", vec!["ab"; 100_000].join("-")));
+        assert_eq!(tag.language(), Some("ab"));
+        assert_eq!(tag.script(), Some("Latn"));
+        assert_eq!(tag.region(), Some("ab"));
+        let canonical = tag.canonical();
+        assert!(canonical.starts_with("ab-Latn-AB-AB-AB"));
+        assert_eq!(canonical.len(), tag.as_str().len());
+        assert_eq!(tag.variants(), Vec::<&str>::new());
+        assert_eq!(tag.extended_language(), None);
+    }
+
+    #[test]
+    fn it_parses_all_subtag_components() {
+        let tag = LanguageTag::parse("zh-yue-Hant-HK-pinyin-u-nu-hanidec-t-en-x-private-a");
+        assert_eq!(tag.language(), Some("zh"));
+        assert_eq!(tag.extended_language(), Some("yue"));
+        assert_eq!(tag.script(), Some("Hant"));
+        assert_eq!(tag.region(), Some("HK"));
+        assert_eq!(tag.variants(), vec!["pinyin"]);
+        assert_eq!(tag.extensions(), vec!["u-nu-hanidec", "t-en"]);
+        assert_eq!(tag.private_use(), Some("x-private-a"));
+
+        let tag = LanguageTag::parse("sl-rozaj-biske-1994");
+        assert_eq!(tag.extended_language(), None);
+        assert_eq!(tag.variants(), vec!["rozaj", "biske", "1994"]);
+        assert_eq!(tag.extensions(), Vec::<&str>::new());
+        assert_eq!(tag.private_use(), None);
+
+        let tag = LanguageTag::parse("X-Klingon");
+        assert_eq!(tag.private_use(), Some("X-Klingon"));
+        assert_eq!(tag.extensions(), Vec::<&str>::new());
+
+        let tag = LanguageTag::parse("*");
+        assert_eq!(tag.variants(), Vec::<&str>::new());
+        assert_eq!(tag.extensions(), Vec::<&str>::new());
+        assert_eq!(tag.private_use(), None);
+    }
+
+    #[test]
+    fn it_only_classifies_subtags_in_their_position() {
+        let tag = LanguageTag::parse("en-US-abc");
+        assert_eq!(tag.extended_language(), None);
+        assert_eq!(LanguageTag::parse("zh-Hant-yue").extended_language(), None);
+        assert_eq!(
+            LanguageTag::parse("sgn-ase").extended_language(),
+            Some("ase")
+        );
+        assert_eq!(LanguageTag::parse("chinese-yue").extended_language(), None);
+
+        let tag = LanguageTag::parse("i-klingon");
+        assert_eq!(tag.extensions(), Vec::<&str>::new());
+        assert_eq!(tag.private_use(), None);
+        let tag = LanguageTag::parse("i-default-u-nu-latn");
+        assert_eq!(tag.extensions(), vec!["u-nu-latn"]);
+    }
+
+    #[test]
+    fn it_keeps_the_order_of_other_subtags_when_canonicalizing() {
+        assert_eq!(
+            LanguageTag::parse("en-Latn-US-001").canonical(),
+            "en-Latn-US-001"
+        );
+        assert_eq!(
+            LanguageTag::parse("de-CH-1901-abc").canonical(),
+            "de-CH-1901-abc"
+        );
+        assert_eq!(
+            LanguageTag::parse("de-1901-Latn-abc").canonical(),
+            "de-Latn-1901-abc"
+        );
+        assert_eq!(
+            LanguageTag::parse("zh-yue-HK-Hant").canonical(),
+            "zh-yue-Hant-HK"
+        );
+    }
+}